@@ -1,6 +1,8 @@
 use std::collections::BTreeMap;
 use std::env;
 
+use intcode::IoDevice;
+
 type Point = (i32, i32);
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -14,11 +16,12 @@ struct Grid {
     current_position: Point,
     current_direction: i8, // up: 0, right: 1, down: 2, left: 3
     painted_points: BTreeMap<Point, Color>,
+    pending_output: Vec<i64>,
 }
 
 impl Grid {
     fn new() -> Self {
-        Grid{current_position: (0, 0), current_direction: 0, painted_points: BTreeMap::new()}
+        Grid{current_position: (0, 0), current_direction: 0, painted_points: BTreeMap::new(), pending_output: Vec::new()}
     }
 
     fn color_at(&self, pt: Point) -> Color {
@@ -60,6 +63,37 @@ impl Grid {
     }
 }
 
+impl IoDevice for Grid {
+    fn read(&mut self) -> Option<i64> {
+        Some(match self.color_at(self.current_position) {
+            Color::White => 1,
+            Color::Black => 0,
+        })
+    }
+
+    fn write(&mut self, value: i64) {
+        self.pending_output.push(value);
+        if self.pending_output.len() < 2 {
+            return;
+        }
+        let color = self.pending_output[0];
+        let direction = self.pending_output[1];
+        self.pending_output.clear();
+
+        match color {
+            0 => self.paint(self.current_position, Color::Black),
+            1 => self.paint(self.current_position, Color::White),
+            _ => panic!("Unexpected color"),
+        }
+
+        match direction {
+            0 => self.turn_left_and_move(),
+            1 => self.turn_right_and_move(),
+            _ => panic!("Unexpected direction"),
+        }
+    }
+}
+
 static PART2: bool = true;
 
 fn main() {
@@ -71,37 +105,7 @@ fn main() {
         grid.paint((0, 0), Color::White);
     }
 
-    loop {
-        let step_result = c.step().unwrap();
-
-        if step_result.done {
-            break;
-        }
-
-        if step_result.input_needed {
-            c.send_input(match grid.color_at(grid.current_position) {
-                Color::White => 1,
-                Color::Black => 0,
-            });
-        }
-
-        if step_result.output_available && c.output.len() == 2 {
-            let color = c.output.pop_front().unwrap();
-            let direction = c.output.pop_front().unwrap();
-
-            match color {
-                0 => grid.paint(grid.current_position, Color::Black),
-                1 => grid.paint(grid.current_position, Color::White),
-                _ => panic!("Unexpected color"),
-            }
-
-            match direction {
-                0 => grid.turn_left_and_move(),
-                1 => grid.turn_right_and_move(),
-                _ => panic!("Unexpected direction"),
-            }
-        }
-    }
+    c.run_with(&mut grid).unwrap();
 
     if PART2 {
         dbg!(&grid.painted_points);
@@ -1,6 +1,8 @@
 use std::collections::BTreeMap;
 use std::env;
 
+use intcode::IoDevice;
+
 fn main() {
     let input_path: &String = &env::args().nth(1).unwrap();
 
@@ -18,9 +20,11 @@ fn main() {
     dbg!(max_x);
     dbg!(max_y);
 
-    let mut game = Game::new(intcode::Computer::load_from_path(input_path).unwrap());
-    game.computer.mem[0] = 2;
-    game.run();
+    let mut c = intcode::Computer::load_from_path(input_path).unwrap();
+    c.mem[0] = 2;
+    let mut game = Game::new();
+    c.run_with(&mut game).unwrap();
+    println!("Score: {}", game.score);
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -77,17 +81,17 @@ impl Tile {
 }
 
 struct Game {
-    computer: intcode::Computer,
     tiles: BTreeMap<(i32, i32), TileType>,
     score: i32,
+    pending_output: Vec<i64>,
 }
 
 impl Game {
-    fn new(computer: intcode::Computer) -> Self {
+    fn new() -> Self {
         Self {
-            computer,
             tiles: BTreeMap::new(),
             score: 0,
+            pending_output: Vec::new(),
         }
     }
 
@@ -119,45 +123,39 @@ impl Game {
 
         None
     }
+}
 
-    fn run(&mut self) {
-        loop {
-            let step_result = self.computer.step().unwrap();
-            if step_result.input_needed {
-                println!("Score: {}", self.score);
-                //self.print_tiles();
-
-                let ball_pos = self.find_tile(TileType::Ball).unwrap();
-                let paddle_pos = self.find_tile(TileType::HorizPaddle).unwrap();
-
-                let input = if ball_pos.0 < paddle_pos.0 {
-                    -1
-                } else if ball_pos.0 > paddle_pos.0 {
-                    1
-                } else {
-                    0
-                };
-
-                self.computer.send_input(input);
-            }
+impl IoDevice for Game {
+    fn read(&mut self) -> Option<i64> {
+        println!("Score: {}", self.score);
+        //self.print_tiles();
 
-            if step_result.output_available && self.computer.output.len() >= 3 {
-                let x = self.computer.output.pop_front().unwrap() as i32;
-                let y = self.computer.output.pop_front().unwrap() as i32;
-                if x == -1 && y == 0 {
-                    self.score = self.computer.output.pop_front().unwrap() as i32;
-                } else {
-                    let tile_type = TileType::from_int(self.computer.output.pop_front().unwrap()).unwrap();
-                    self.tiles.insert((x, y), tile_type);
-                }
-            }
+        let ball_pos = self.find_tile(TileType::Ball).unwrap();
+        let paddle_pos = self.find_tile(TileType::HorizPaddle).unwrap();
 
-            if step_result.done {
-                println!("Score: {}", self.score);
-                break;
-            }
+        Some(if ball_pos.0 < paddle_pos.0 {
+            -1
+        } else if ball_pos.0 > paddle_pos.0 {
+            1
+        } else {
+            0
+        })
+    }
+
+    fn write(&mut self, value: i64) {
+        self.pending_output.push(value);
+        if self.pending_output.len() < 3 {
+            return;
+        }
+
+        let x = self.pending_output[0] as i32;
+        let y = self.pending_output[1] as i32;
+        if x == -1 && y == 0 {
+            self.score = self.pending_output[2] as i32;
+        } else {
+            let tile_type = TileType::from_int(self.pending_output[2]).unwrap();
+            self.tiles.insert((x, y), tile_type);
         }
-    
-    
+        self.pending_output.clear();
     }
 }
@@ -113,25 +113,17 @@ impl Droid {
         assert!(self.computer.input.is_empty());
         self.computer.input.push_back(direction.to_int());
 
-        loop {
-            let step_result = self.computer.step()?;
-
-            assert!(!step_result.input_needed);
-            assert!(!step_result.done);
-            if step_result.output_available {
-                let output = self.computer.take_output();
-                let status = Status::from_int(output[0])?;
+        let output = self.computer.run_until_output(1).map_err(|e| e.to_string())?.map_err(|stopped| stopped.to_string())?;
+        let status = Status::from_int(output[0])?;
 
-                if status != Status::HitAWall {
-                    self.current_position = self.current_position + direction.vector();
-                }
+        if status != Status::HitAWall {
+            self.current_position = self.current_position + direction.vector();
+        }
 
-                return match status {
-                    Status::Moved => Ok((status, self.current_position)),
-                    Status::HitAWall => Ok((status, self.current_position + direction.vector())),
-                    Status::FoundTheThing => Ok((status, self.current_position)),
-                };
-            }
+        match status {
+            Status::Moved => Ok((status, self.current_position)),
+            Status::HitAWall => Ok((status, self.current_position + direction.vector())),
+            Status::FoundTheThing => Ok((status, self.current_position)),
         }
     }
 }
@@ -1,11 +1,9 @@
 use std::env;
-use std::fs;
-use std::io;
 use intcode;
 
 fn main() {
     let input_path: &String = &env::args().nth(1).unwrap();
-    let input_data = read_input(&input_path).unwrap();
+    let input_data = intcode::read_from_path(&input_path).unwrap();
 
     let mut c = intcode::Computer::new(input_data.clone());
     c.mem[1] = 12;
@@ -16,6 +14,7 @@ fn main() {
     for i in 0..100 {
         for j in 0..100 {
             let mut c = intcode::Computer::new(input_data.clone());
+            c.set_fuel(Some(100_000));
             c.mem[1] = i;
             c.mem[2] = j;
             if let Ok(_) = c.run() {
@@ -29,9 +28,3 @@ fn main() {
     }
 }
 
-fn read_input(path: &str) -> io::Result<Vec<i64>> {
-    let contents = fs::read_to_string(path)?;
-    let numbers: Vec<i64> = contents.trim().split(',').map(|x| x.parse::<i64>().unwrap()).collect();
-    Ok(numbers)
-}
-
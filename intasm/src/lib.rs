@@ -0,0 +1,252 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use intcode::{InParam, Inst, OutParam, Word};
+
+// Assembly source is one statement per line:
+//
+//   loop:   IN pos:x            # '#' starts a comment
+//           JT pos:x imm:loop
+//           HALT
+//   x:      .data 0             # a named data cell
+//
+// Operands use the same pos:/imm:/rel: syntax that InParam displays, and the
+// value can be a number, a label, or label+N / label-N.
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AsmErrorKind {
+    UnknownMnemonic(String),
+    UnknownDirective(String),
+    WrongOperandCount { mnemonic: String, expected: usize, found: usize },
+    BadOperand(String),
+    BadValue(String),
+    ImmediateDestination(String),
+    BadLabel(String),
+    DuplicateLabel(String),
+    UndefinedLabel(String),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub kind: AsmErrorKind,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            AsmErrorKind::UnknownMnemonic(s) => write!(f, "unknown mnemonic {:?}", s),
+            AsmErrorKind::UnknownDirective(s) => write!(f, "unknown directive {:?}", s),
+            AsmErrorKind::WrongOperandCount { mnemonic, expected, found } =>
+                write!(f, "{} takes {} operands, found {}", mnemonic, expected, found),
+            AsmErrorKind::BadOperand(s) => write!(f, "bad operand {:?}", s),
+            AsmErrorKind::BadValue(s) => write!(f, "bad value {:?}", s),
+            AsmErrorKind::ImmediateDestination(s) => write!(f, "{:?} cannot be written to", s),
+            AsmErrorKind::BadLabel(s) => write!(f, "bad label {:?}", s),
+            AsmErrorKind::DuplicateLabel(s) => write!(f, "label {:?} is already defined", s),
+            AsmErrorKind::UndefinedLabel(s) => write!(f, "label {:?} is not defined", s),
+        }
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+const MNEMONICS: [(&str, usize); 10] = [
+    ("ADD", 3),
+    ("MULT", 3),
+    ("IN", 1),
+    ("OUT", 1),
+    ("JT", 2),
+    ("JF", 2),
+    ("LT", 3),
+    ("EQ", 3),
+    ("ARB", 1),
+    ("HALT", 0),
+];
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Value {
+    Number(Word),
+    Label(String, Word),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Mode {
+    Position,
+    Immediate,
+    Relative,
+}
+
+#[derive(Clone, Debug)]
+struct Operand {
+    text: String,
+    mode: Mode,
+    value: Value,
+}
+
+#[derive(Clone, Debug)]
+enum Statement {
+    Inst { mnemonic: &'static str, operands: Vec<Operand> },
+    Data(Vec<Value>),
+}
+
+impl Statement {
+    fn len(&self) -> usize {
+        match self {
+            Statement::Inst { operands, .. } => operands.len() + 1,
+            Statement::Data(values) => values.len(),
+        }
+    }
+}
+
+pub fn assemble(source: &str) -> Result<Vec<Word>, AsmError> {
+    let mut labels: BTreeMap<String, Word> = BTreeMap::new();
+    let mut statements: Vec<(usize, Statement)> = Vec::new();
+    let mut address = 0;
+
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let err = |kind| AsmError { line: line_number, kind };
+
+        let code = line.split('#').next().unwrap_or("");
+        let mut tokens: Vec<&str> = code
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|t| !t.is_empty())
+            .collect();
+
+        while let Some(label) = tokens.first().and_then(|t| t.strip_suffix(':')) {
+            if !is_identifier(label) {
+                return Err(err(AsmErrorKind::BadLabel(label.to_string())));
+            }
+            if labels.insert(label.to_string(), address as Word).is_some() {
+                return Err(err(AsmErrorKind::DuplicateLabel(label.to_string())));
+            }
+            tokens.remove(0);
+        }
+
+        let statement = match tokens.split_first() {
+            None => continue,
+            Some((&".data", values)) => {
+                let values = values.iter()
+                    .map(|v| parse_value(v).ok_or_else(|| err(AsmErrorKind::BadValue(v.to_string()))))
+                    .collect::<Result<Vec<Value>, AsmError>>()?;
+                Statement::Data(values)
+            }
+            Some((directive, _)) if directive.starts_with('.') => {
+                return Err(err(AsmErrorKind::UnknownDirective(directive.to_string())));
+            }
+            Some((name, operands)) => {
+                let upper = name.to_ascii_uppercase();
+                let &(mnemonic, arity) = MNEMONICS.iter()
+                    .find(|(m, _)| *m == upper)
+                    .ok_or_else(|| err(AsmErrorKind::UnknownMnemonic(name.to_string())))?;
+                if operands.len() != arity {
+                    return Err(err(AsmErrorKind::WrongOperandCount {
+                        mnemonic: mnemonic.to_string(),
+                        expected: arity,
+                        found: operands.len(),
+                    }));
+                }
+                let operands = operands.iter()
+                    .map(|o| parse_operand(o).ok_or_else(|| err(AsmErrorKind::BadOperand(o.to_string()))))
+                    .collect::<Result<Vec<Operand>, AsmError>>()?;
+                Statement::Inst { mnemonic, operands }
+            }
+        };
+
+        address += statement.len();
+        statements.push((line_number, statement));
+    }
+
+    let mut program = Vec::with_capacity(address);
+    for (line, statement) in statements {
+        let err = |kind| AsmError { line, kind };
+        let resolve = |value: &Value| match value {
+            Value::Number(n) => Ok(*n),
+            Value::Label(name, offset) => match labels.get(name) {
+                Some(address) => address.checked_add(*offset)
+                    .ok_or_else(|| err(AsmErrorKind::BadValue(format!("{}{:+}", name, offset)))),
+                None => Err(err(AsmErrorKind::UndefinedLabel(name.clone()))),
+            },
+        };
+
+        match statement {
+            Statement::Data(values) => {
+                for value in values.iter() {
+                    program.push(resolve(value)?);
+                }
+            }
+            Statement::Inst { mnemonic, operands } => {
+                let values = operands.iter()
+                    .map(|o| resolve(&o.value))
+                    .collect::<Result<Vec<Word>, AsmError>>()?;
+                let src = |i: usize| match operands[i].mode {
+                    Mode::Position => InParam::Position(values[i]),
+                    Mode::Immediate => InParam::Immediate(values[i]),
+                    Mode::Relative => InParam::Relative(values[i]),
+                };
+                let dst = |i: usize| match operands[i].mode {
+                    Mode::Position => Ok(OutParam::Position(values[i])),
+                    Mode::Immediate => Err(err(AsmErrorKind::ImmediateDestination(operands[i].text.clone()))),
+                    Mode::Relative => Ok(OutParam::Relative(values[i])),
+                };
+
+                let inst = match mnemonic {
+                    "ADD" => Inst::Add(src(0), src(1), dst(2)?),
+                    "MULT" => Inst::Mult(src(0), src(1), dst(2)?),
+                    "IN" => Inst::Input(dst(0)?),
+                    "OUT" => Inst::Output(src(0)),
+                    "JT" => Inst::JumpIfTrue(src(0), src(1)),
+                    "JF" => Inst::JumpIfFalse(src(0), src(1)),
+                    "LT" => Inst::LessThan(src(0), src(1), dst(2)?),
+                    "EQ" => Inst::Equal(src(0), src(1), dst(2)?),
+                    "ARB" => Inst::AdjustBase(src(0)),
+                    "HALT" => Inst::Exit,
+                    _ => unreachable!(),
+                };
+                program.extend(inst.encode());
+            }
+        }
+    }
+
+    Ok(program)
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_value(s: &str) -> Option<Value> {
+    if let Ok(n) = s.parse::<Word>() {
+        return Some(Value::Number(n));
+    }
+
+    let (name, offset) = match s.find(['+', '-']) {
+        Some(i) => (&s[..i], s[i..].trim_start_matches('+').parse::<Word>().ok()?),
+        None => (s, 0),
+    };
+    if !is_identifier(name) {
+        return None;
+    }
+    Some(Value::Label(name.to_string(), offset))
+}
+
+fn parse_operand(s: &str) -> Option<Operand> {
+    let (mode, value) = s.split_at(s.find(':')?);
+    let mode = match mode {
+        "pos" => Mode::Position,
+        "imm" => Mode::Immediate,
+        "rel" => Mode::Relative,
+        _ => return None,
+    };
+    Some(Operand { text: s.to_string(), mode, value: parse_value(&value[1..])? })
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,18 @@
+use std::env;
+use std::fs;
+
+fn main() {
+    let input_path: &String = &env::args().nth(1).unwrap();
+    let source = fs::read_to_string(input_path).unwrap();
+
+    match intasm::assemble(&source) {
+        Ok(program) => {
+            let words: Vec<String> = program.iter().map(|w| w.to_string()).collect();
+            println!("{}", words.join(","));
+        }
+        Err(e) => {
+            eprintln!("{}: {}", input_path, e);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use super::*;
+
+fn run(program: Vec<Word>, input: Vec<Word>) -> Vec<Word> {
+    let mut c = intcode::Computer::new(program);
+    c.input.extend(input);
+    c.run().unwrap();
+    c.take_output()
+}
+
+#[test]
+fn assemble_matches_hand_encoding() {
+    let p = assemble("
+        IN pos:15
+        IN pos:16
+        MULT pos:16 imm:10 pos:16
+        ADD pos:16 pos:15 pos:15
+        OUT pos:15
+        HALT
+    ").unwrap();
+    assert_eq!(p, vec!(3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99));
+}
+
+#[test]
+fn assemble_labels_and_data() {
+    let p = assemble("
+        # count down from the input value
+                IN pos:counter
+        loop:   OUT pos:counter
+                ADD pos:counter imm:-1 pos:counter
+                JT pos:counter imm:loop
+                HALT
+        counter: .data 0
+    ").unwrap();
+    assert_eq!(p, vec!(3,12,4,12,1001,12,-1,12,1005,12,2,99,0));
+    assert_eq!(run(p, vec!(3)), vec!(3,2,1));
+}
+
+#[test]
+fn assemble_relative_and_offsets() {
+    let p = assemble("
+        ARB imm:table
+        OUT rel:1
+        OUT pos:table+2
+        HALT
+        table: .data 10, 20, 30
+    ").unwrap();
+    assert_eq!(run(p, vec!()), vec!(20, 30));
+}
+
+#[test]
+fn assemble_errors() {
+    let e = assemble("HALT\nFOO imm:1").unwrap_err();
+    assert_eq!(e, AsmError { line: 2, kind: AsmErrorKind::UnknownMnemonic("FOO".to_string()) });
+    assert_eq!(e.to_string(), "line 2: unknown mnemonic \"FOO\"");
+
+    let e = assemble("ADD imm:1 imm:2").unwrap_err();
+    assert_eq!(e.kind, AsmErrorKind::WrongOperandCount { mnemonic: "ADD".to_string(), expected: 3, found: 2 });
+
+    let e = assemble("IN imm:3").unwrap_err();
+    assert_eq!(e.kind, AsmErrorKind::ImmediateDestination("imm:3".to_string()));
+
+    let e = assemble("JT imm:1 imm:nowhere").unwrap_err();
+    assert_eq!(e.kind, AsmErrorKind::UndefinedLabel("nowhere".to_string()));
+
+    let e = assemble("a: HALT\na: HALT").unwrap_err();
+    assert_eq!(e, AsmError { line: 2, kind: AsmErrorKind::DuplicateLabel("a".to_string()) });
+
+    let e = assemble("OUT foo:1").unwrap_err();
+    assert_eq!(e.kind, AsmErrorKind::BadOperand("foo:1".to_string()));
+
+    let e = assemble("HALT\nx: OUT imm:x+9223372036854775807").unwrap_err();
+    assert_eq!(e, AsmError { line: 2, kind: AsmErrorKind::BadValue("x+9223372036854775807".to_string()) });
+}
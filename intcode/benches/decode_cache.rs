@@ -0,0 +1,66 @@
+use std::time::{Duration, Instant};
+
+use intcode::{Computer, Topology, Word};
+
+// Counts [8] down to zero.
+fn countdown(n: Word) -> Vec<Word> {
+    vec!(1001,8,-1,8,1005,8,0,99,n)
+}
+
+// The feedback loop example from day 7.
+const AMPS: &str = "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5";
+
+fn permutations(items: &[Word]) -> Vec<Vec<Word>> {
+    if items.len() <= 1 {
+        return vec!(items.to_vec());
+    }
+    let mut result = Vec::new();
+    for i in 0..items.len() {
+        let mut rest = items.to_vec();
+        let first = rest.remove(i);
+        for mut p in permutations(&rest) {
+            p.insert(0, first);
+            result.push(p);
+        }
+    }
+    result
+}
+
+fn time<F: FnMut(bool) -> Word>(name: &str, mut f: F) {
+    const RUNS: u32 = 5;
+    let mut times = [Duration::default(); 2];
+    let mut results = [0; 2];
+    for _ in 0..RUNS {
+        for (i, cached) in [false, true].iter().enumerate() {
+            let start = Instant::now();
+            results[i] = f(*cached);
+            times[i] += start.elapsed();
+        }
+    }
+    assert_eq!(results[0], results[1], "{} gave different answers", name);
+    println!("{:<12} uncached {:>10.2?}  cached {:>10.2?}  speedup {:.2}x",
+        name, times[0] / RUNS, times[1] / RUNS, times[0].as_secs_f64() / times[1].as_secs_f64());
+}
+
+fn main() {
+    time("countdown", |cached| {
+        let mut c = Computer::new(countdown(1_000_000));
+        if cached {
+            c.enable_decode_cache();
+        }
+        c.run().unwrap();
+        c.instructions_executed() as Word
+    });
+
+    let p = intcode::read_from_string(AMPS).unwrap();
+    let phases = permutations(&[5, 6, 7, 8, 9]);
+    time("amplifiers", |cached| {
+        phases.iter().map(|phases| {
+            let mut amps = Topology::ring(&p, phases);
+            if cached {
+                amps.computers_mut().for_each(|c| c.enable_decode_cache());
+            }
+            *amps.run(&[0]).unwrap().unwrap().last().unwrap()
+        }).max().unwrap()
+    });
+}
@@ -0,0 +1,276 @@
+use std::convert::TryFrom;
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::disasm::reachable;
+use crate::{Computer, InParam, Inst, OutParam, Word};
+
+// Translates a program into Rust source with a `match pc` arm for every
+// instruction reachable from 0, meant to be written out by a build script
+// and pulled in with include!. The generated items are:
+//
+//   PROGRAM: the program itself
+//   new(): a Computer loaded with PROGRAM
+//   run(c): does what c.run() does, but runs the compiled code
+//
+// Everything else about the Computer (input, output, send_input and so on)
+// works as usual. run() hands over to the interpreter when it gets somewhere
+// the compiler didn't see (like a jump through memory), when the program
+// writes over its own code, and when the Computer has fuel, a deadline,
+// tracing, history or watchpoints, none of which the compiled code checks.
+// Whether the code is unchanged is remembered until the next write to
+// memory, so after changing c.mem directly, call c.invalidate_decode_cache().
+pub fn compile(p: &[Word]) -> String {
+    let code = reachable(p);
+    let mut out = String::new();
+
+    // Writing to a String can't fail.
+    let _ = emit(&mut out, p, &code_ranges(&code), &code);
+    out
+}
+
+// Writes the compiled program to path, unless it's already there, so that a
+// build script doesn't cause a rebuild every time it runs.
+pub fn compile_to_file<P: AsRef<Path>>(p: &[Word], path: P) -> std::io::Result<()> {
+    let source = compile(p);
+    if std::fs::read_to_string(path.as_ref()).ok().as_deref() == Some(source.as_str()) {
+        return Ok(());
+    }
+    std::fs::write(path, source)
+}
+
+// Merges the words of every reachable instruction into (start, end) ranges.
+fn code_ranges(code: &std::collections::BTreeMap<usize, Inst>) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for (&pc, inst) in code.iter() {
+        let end = pc + inst.len();
+        match ranges.last_mut() {
+            Some(last) if pc <= last.1 => last.1 = last.1.max(end),
+            _ => ranges.push((pc, end)),
+        }
+    }
+    ranges
+}
+
+fn emit(out: &mut String, p: &[Word], ranges: &[(usize, usize)], code: &std::collections::BTreeMap<usize, Inst>) -> std::fmt::Result {
+    writeln!(out, "// Generated by intcode::aot::compile. Don't edit.")?;
+    writeln!(out)?;
+
+    let words: Vec<String> = p.iter().map(|w| w.to_string()).collect();
+    // A static rather than a const, so it has one address for can_run to
+    // tell it apart by.
+    writeln!(out, "pub static PROGRAM: [intcode::Word; {}] = [", p.len())?;
+    for line in words.chunks(16) {
+        writeln!(out, "    {},", line.join(", "))?;
+    }
+    writeln!(out, "];")?;
+    writeln!(out)?;
+
+    let ranges: Vec<String> = ranges.iter().map(|(start, end)| format!("({}, {})", start, end)).collect();
+    writeln!(out, "const CODE: &[(usize, usize)] = &[{}];", ranges.join(", "))?;
+    writeln!(out)?;
+
+    writeln!(out, "pub fn new() -> intcode::Computer {{")?;
+    writeln!(out, "    intcode::Computer::new(PROGRAM.to_vec())")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+
+    writeln!(out, "pub fn run(c: &mut intcode::Computer) -> Result<intcode::StepResult, intcode::Error> {{")?;
+    writeln!(out, "    if !intcode::aot::can_run(c, &PROGRAM, CODE) {{")?;
+    writeln!(out, "        return c.run();")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "    let mut steps = 0;")?;
+    writeln!(out, "    let result = exec(c, &mut steps);")?;
+    writeln!(out, "    intcode::aot::__rt::count_steps(c, steps);")?;
+    writeln!(out, "    result")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+
+    // A program that can't get past its first instruction never reaches the
+    // end of the loop.
+    writeln!(out, "#[allow(unreachable_code, unused_imports)]")?;
+    writeln!(out, "fn exec(c: &mut intcode::Computer, steps: &mut u64) -> Result<intcode::StepResult, intcode::Error> {{")?;
+    writeln!(out, "    use intcode::aot::__rt;")?;
+    writeln!(out, "    loop {{")?;
+    writeln!(out, "        match c.pc {{")?;
+    for (&pc, inst) in code.iter() {
+        writeln!(out, "            // {}", inst)?;
+        writeln!(out, "            {} => {{", pc)?;
+        emit_inst(out, inst, pc + inst.len())?;
+        writeln!(out, "            }}")?;
+    }
+    writeln!(out, "            _ => return c.run(),")?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "        *steps += 1;")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")
+}
+
+const INDENT: &str = "                ";
+
+fn emit_inst(out: &mut String, inst: &Inst, next: usize) -> std::fmt::Result {
+    match *inst {
+        Inst::Add(a, b, dst) => {
+            emit_operands(out, a, b)?;
+            writeln!(out, "{}let value = __rt::add(c, lhs, rhs)?;", INDENT)?;
+            emit_store(out, dst, next)?;
+        }
+        Inst::Mult(a, b, dst) => {
+            emit_operands(out, a, b)?;
+            writeln!(out, "{}let value = __rt::mul(c, lhs, rhs)?;", INDENT)?;
+            emit_store(out, dst, next)?;
+        }
+        Inst::LessThan(a, b, dst) => {
+            emit_operands(out, a, b)?;
+            writeln!(out, "{}let value = (lhs < rhs) as intcode::Word;", INDENT)?;
+            emit_store(out, dst, next)?;
+        }
+        Inst::Equal(a, b, dst) => {
+            emit_operands(out, a, b)?;
+            writeln!(out, "{}let value = (lhs == rhs) as intcode::Word;", INDENT)?;
+            emit_store(out, dst, next)?;
+        }
+        Inst::Input(dst) => {
+            writeln!(out, "{}let value = match c.input.pop_front() {{", INDENT)?;
+            writeln!(out, "{}    Some(value) => value,", INDENT)?;
+            writeln!(out, "{}    None => return Ok(__rt::input_needed(c)),", INDENT)?;
+            writeln!(out, "{}}};", INDENT)?;
+            emit_store(out, dst, next)?;
+        }
+        Inst::Output(a) => {
+            writeln!(out, "{}let value: intcode::Word = {};", INDENT, operand(a))?;
+            writeln!(out, "{}c.output.push_back(value);", INDENT)?;
+            writeln!(out, "{}c.pc = {};", INDENT, next)?;
+        }
+        Inst::JumpIfTrue(cond, target) => emit_jump(out, cond, target, "!=", next)?,
+        Inst::JumpIfFalse(cond, target) => emit_jump(out, cond, target, "==", next)?,
+        Inst::AdjustBase(a) => {
+            writeln!(out, "{}let offset: intcode::Word = {};", INDENT, operand(a))?;
+            writeln!(out, "{}c.relative_base = __rt::relative(c, offset)?;", INDENT)?;
+            writeln!(out, "{}c.pc = {};", INDENT, next)?;
+        }
+        Inst::Exit => writeln!(out, "{}return Ok(__rt::halted(c));", INDENT)?,
+    }
+    Ok(())
+}
+
+fn emit_operands(out: &mut String, a: InParam, b: InParam) -> std::fmt::Result {
+    writeln!(out, "{}let lhs: intcode::Word = {};", INDENT, operand(a))?;
+    writeln!(out, "{}let rhs: intcode::Word = {};", INDENT, operand(b))
+}
+
+// Operands are evaluated in the same order as the interpreter does it, so
+// errors come out the same.
+fn emit_jump(out: &mut String, cond: InParam, target: InParam, compare: &str, next: usize) -> std::fmt::Result {
+    writeln!(out, "{}let cond: intcode::Word = {};", INDENT, operand(cond))?;
+    let target = match target {
+        InParam::Immediate(t) if usize::try_from(t).is_ok() => t.to_string(),
+        _ => format!("__rt::jump_target(c, {})?", operand(target)),
+    };
+    writeln!(out, "{}let target = {};", INDENT, target)?;
+    writeln!(out, "{}c.pc = if cond {} 0 {{ target }} else {{ {} }};", INDENT, compare, next)
+}
+
+fn emit_store(out: &mut String, dst: OutParam, next: usize) -> std::fmt::Result {
+    let address = match dst {
+        OutParam::Position(i) => i.to_string(),
+        OutParam::Relative(i) => format!("__rt::relative(c, {})?", i),
+    };
+    writeln!(out, "{}let into_code = __rt::store(c, CODE, {}, value)?;", INDENT, address)?;
+    writeln!(out, "{}c.pc = {};", INDENT, next)?;
+    writeln!(out, "{}if into_code {{", INDENT)?;
+    writeln!(out, "{}    *steps += 1;", INDENT)?;
+    writeln!(out, "{}    return c.run();", INDENT)?;
+    writeln!(out, "{}}}", INDENT)
+}
+
+fn operand(param: InParam) -> String {
+    match param {
+        InParam::Immediate(i) => i.to_string(),
+        InParam::Position(i) if usize::try_from(i).is_ok() => format!("c.read_mem({})", i),
+        InParam::Position(i) => format!("__rt::load(c, {})?", i),
+        InParam::Relative(i) => format!("__rt::load(c, __rt::relative(c, {})?)?", i),
+    }
+}
+
+// Whether compiled code can run this Computer: nothing is enabled that it
+// doesn't know about, and the code it was compiled from hasn't changed.
+pub fn can_run(c: &mut Computer, program: &[Word], code: &[(usize, usize)]) -> bool {
+    if c.fuel.is_some() || c.deadline.is_some() || c.tracer.is_some() || c.history.is_some()
+        || c.watchpoints.iter().any(Option::is_some) {
+        return false;
+    }
+
+    // Programs are told apart by where they live, which is fine as long as
+    // they're the static PROGRAM of some compiled code. Two programs can
+    // only share an address if one is empty, so the length goes in too.
+    let id = (program.as_ptr() as usize, program.len());
+    if c.compiled_code == Some(id) {
+        return true;
+    }
+    let unchanged = code.iter().all(|&(start, end)| c.mem.get(start..end) == program.get(start..end));
+    if unchanged {
+        c.compiled_code = Some(id);
+    }
+    unchanged
+}
+
+// What compiled code calls back into, and nothing else should. The current
+// instruction is always c.pc, so errors have the right pc.
+#[doc(hidden)]
+pub mod __rt {
+    use std::convert::TryFrom;
+
+    use crate::{Computer, Error, StepResult, Word};
+
+    pub fn is_code(code: &[(usize, usize)], address: usize) -> bool {
+        let i = code.partition_point(|&(_, end)| end <= address);
+        code.get(i).is_some_and(|&(start, _)| start <= address)
+    }
+
+    pub fn count_steps(c: &mut Computer, steps: u64) {
+        c.instructions_executed += steps;
+    }
+
+    pub fn relative(c: &Computer, offset: Word) -> Result<Word, Error> {
+        c.relative_address(&offset)
+    }
+
+    pub fn load(c: &Computer, address: Word) -> Result<Word, Error> {
+        let address = usize::try_from(address).map_err(|_| Error::BadLoadAddress { pc: c.pc, address })?;
+        Ok(c.read_mem(address))
+    }
+
+    // Returns whether the store was into code. If it wasn't, the code is
+    // still known to be unchanged, so can_run doesn't have to check again.
+    pub fn store(c: &mut Computer, code: &[(usize, usize)], address: Word, value: Word) -> Result<bool, Error> {
+        let address = usize::try_from(address).map_err(|_| Error::BadStoreAddress { pc: c.pc, address })?;
+        let compiled_code = c.compiled_code;
+        c.write_word(address, value)?;
+        let into_code = is_code(code, address);
+        if !into_code {
+            c.compiled_code = compiled_code;
+        }
+        Ok(into_code)
+    }
+
+    pub fn jump_target(c: &Computer, target: Word) -> Result<usize, Error> {
+        usize::try_from(target).map_err(|_| Error::BadJumpTarget { pc: c.pc, target })
+    }
+
+    pub fn add(c: &Computer, lhs: Word, rhs: Word) -> Result<Word, Error> {
+        c.overflow.add(c.pc, lhs, rhs)
+    }
+
+    pub fn mul(c: &Computer, lhs: Word, rhs: Word) -> Result<Word, Error> {
+        c.overflow.mul(c.pc, lhs, rhs)
+    }
+
+    pub fn input_needed(c: &mut Computer) -> StepResult {
+        StepResult::input_needed(c)
+    }
+
+    pub fn halted(c: &mut Computer) -> StepResult {
+        StepResult::done(c)
+    }
+}
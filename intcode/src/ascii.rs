@@ -0,0 +1,83 @@
+use std::io::{self, BufRead, Write};
+
+use crate::{Computer, StepResult, Word};
+
+// Output split into text and the values that aren't ASCII characters, which
+// are usually answers too big to be printed as text.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct AsciiOutput {
+    pub text: String,
+    pub values: Vec<Word>,
+}
+
+impl AsciiOutput {
+    pub fn lines(&self) -> Vec<&str> {
+        self.text.lines().collect()
+    }
+}
+
+fn is_ascii(value: Word) -> bool {
+    (0..128).contains(&value)
+}
+
+impl Computer {
+    // Queues the character codes of s exactly as given.
+    pub fn send_ascii(&mut self, s: &str) {
+        self.input.extend(s.bytes().map(Word::from));
+    }
+
+    // Queues s followed by a newline, leaving out any line ending it already
+    // has so that "\r\n" from a terminal comes out as "\n".
+    pub fn send_line(&mut self, s: &str) {
+        self.send_ascii(s.trim_end_matches(&['\r', '\n'][..]));
+        self.send_ascii("\n");
+    }
+
+    pub fn take_ascii_output(&mut self) -> AsciiOutput {
+        let mut result = AsciiOutput::default();
+        for value in self.output.drain(..) {
+            if is_ascii(value) {
+                result.text.push(value as u8 as char);
+            } else {
+                result.values.push(value);
+            }
+        }
+        result
+    }
+
+    // Runs the program, printing its text output and sending it lines read
+    // from input whenever it wants more. Values that aren't ASCII are
+    // printed as numbers on lines of their own. Returns when the program
+    // halts or input runs out.
+    pub fn interact<R: BufRead, W: Write>(&mut self, mut input: R, mut output: W) -> io::Result<StepResult> {
+        // Numbers go on a line of their own.
+        let mut line_start = true;
+        loop {
+            let result = self.run().map_err(io::Error::other)?;
+            for value in self.output.drain(..) {
+                if is_ascii(value) {
+                    output.write_all(&[value as u8])?;
+                    line_start = value == Word::from(b'\n');
+                } else {
+                    if !line_start {
+                        writeln!(output)?;
+                    }
+                    writeln!(output, "{}", value)?;
+                    line_start = true;
+                }
+            }
+            output.flush()?;
+
+            if result.done {
+                return Ok(result);
+            }
+            if result.input_needed {
+                let mut line = String::new();
+                if input.read_line(&mut line)? == 0 {
+                    return Ok(result);
+                }
+                self.send_line(&line);
+            }
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+
+use crate::{Computer, Error, Stopped, Word};
+
+// How many instructions to run before giving other tasks a turn. A long
+// stretch of computation shouldn't hold up the executor thread.
+const STEPS_PER_POLL: usize = 10_000;
+
+// Runs a Computer as a stream of its outputs, reading input from another
+// stream whenever the program asks for it. The stream ends when the program
+// halts or fails, or when it needs input and the input stream has ended;
+// stopped says which. It works with any executor.
+pub struct AsyncComputer<S> {
+    computer: Computer,
+    input: S,
+    finished: bool,
+    stopped: Option<Stopped>,
+}
+
+impl<S: Stream<Item = Word> + Unpin> AsyncComputer<S> {
+    pub fn new(computer: Computer, input: S) -> Self {
+        AsyncComputer { computer, input, finished: false, stopped: None }
+    }
+
+    // Stopped::Halted or Stopped::InputNeeded once the stream has ended
+    // without an error.
+    pub fn stopped(&self) -> Option<Stopped> {
+        self.stopped
+    }
+
+    pub fn computer(&self) -> &Computer {
+        &self.computer
+    }
+
+    pub fn into_inner(self) -> Computer {
+        self.computer
+    }
+
+    // Runs to the end and returns every output, unless the input stream ran
+    // out before the program halted.
+    pub async fn run_to_end(&mut self) -> Result<Result<Vec<Word>, Stopped>, Error> {
+        let mut outputs = Vec::new();
+        while let Some(value) = std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await {
+            outputs.push(value?);
+        }
+        Ok(match self.stopped {
+            Some(Stopped::InputNeeded) => Err(Stopped::InputNeeded),
+            _ => Ok(outputs),
+        })
+    }
+}
+
+impl<S: Stream<Item = Word> + Unpin> Stream for AsyncComputer<S> {
+    type Item = Result<Word, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.finished {
+            return Poll::Ready(None);
+        }
+
+        for _ in 0..STEPS_PER_POLL {
+            if let Some(value) = this.computer.output.pop_front() {
+                return Poll::Ready(Some(Ok(value)));
+            }
+            let result = match this.computer.step() {
+                Ok(result) => result,
+                Err(e) => {
+                    this.finished = true;
+                    return Poll::Ready(Some(Err(e)));
+                }
+            };
+            if result.done {
+                this.finished = true;
+                this.stopped = Some(Stopped::Halted);
+                return Poll::Ready(None);
+            }
+            if result.input_needed {
+                match Pin::new(&mut this.input).poll_next(cx) {
+                    Poll::Ready(Some(value)) => this.computer.send_input(value),
+                    Poll::Ready(None) => {
+                        this.finished = true;
+                        this.stopped = Some(Stopped::InputNeeded);
+                        return Poll::Ready(None);
+                    }
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+        }
+
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+#[cfg(feature = "tokio")]
+pub mod tokio {
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    use futures_core::Stream;
+    use tokio::sync::mpsc;
+    use tokio::task::JoinHandle;
+
+    use super::AsyncComputer;
+    use crate::{Computer, Error, Stopped, Word};
+
+    // Lets a tokio channel be used as the input to an AsyncComputer.
+    pub struct ReceiverStream(pub mpsc::UnboundedReceiver<Word>);
+
+    impl Stream for ReceiverStream {
+        type Item = Word;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Word>> {
+            self.0.poll_recv(cx)
+        }
+    }
+
+    // What spawn's task gives back.
+    pub type Task = JoinHandle<Result<(Computer, Stopped), Error>>;
+
+    // Runs a Computer as a tokio task. Outputs arrive on the returned
+    // receiver as they're produced, and the task gives back the Computer
+    // and why it stopped: it halted, or it needed input after the sender was
+    // dropped.
+    pub fn spawn(computer: Computer) -> (mpsc::UnboundedSender<Word>, mpsc::UnboundedReceiver<Word>, Task) {
+        let (input_tx, input_rx) = mpsc::unbounded_channel();
+        let (output_tx, output_rx) = mpsc::unbounded_channel();
+        let handle = tokio::spawn(async move {
+            let mut driver = AsyncComputer::new(computer, ReceiverStream(input_rx));
+            while let Some(value) = std::future::poll_fn(|cx| Pin::new(&mut driver).poll_next(cx)).await {
+                let _ = output_tx.send(value?);
+            }
+            let stopped = driver.stopped().unwrap_or(Stopped::Halted);
+            Ok((driver.into_inner(), stopped))
+        });
+        (input_tx, output_rx, handle)
+    }
+}
@@ -0,0 +1,13 @@
+use std::env;
+
+// Compiles a program to Rust source. Prints it, or writes it to the second
+// argument if there is one.
+fn main() {
+    let input_path: &String = &env::args().nth(1).unwrap();
+    let p = intcode::read_from_path(input_path).unwrap();
+
+    match env::args().nth(2) {
+        Some(output_path) => intcode::aot::compile_to_file(&p, output_path).unwrap(),
+        None => print!("{}", intcode::aot::compile(&p)),
+    }
+}
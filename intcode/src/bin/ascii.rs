@@ -0,0 +1,18 @@
+use std::env;
+use std::io;
+
+// Runs an ASCII program at the terminal. Any extra arguments are sent as
+// lines of input before reading from stdin.
+fn main() {
+    let input_path: &String = &env::args().nth(1).unwrap();
+    let mut computer = intcode::Computer::load_from_path(input_path).unwrap();
+    for arg in env::args().skip(2) {
+        computer.send_line(&arg);
+    }
+
+    let stdin = io::stdin();
+    let result = computer.interact(stdin.lock(), io::stdout()).unwrap();
+    if !result.done {
+        eprintln!("program is still waiting for input");
+    }
+}
@@ -0,0 +1,49 @@
+use std::env;
+use std::io::{self, BufRead, Write};
+
+use intcode::debugger::{parse_command, Command, Debugger};
+
+fn main() {
+    let input_path: &String = &env::args().nth(1).unwrap();
+    let mut computer = intcode::Computer::load_from_path(input_path).unwrap();
+    for arg in env::args().skip(2) {
+        computer.send_input(arg.parse().unwrap());
+    }
+
+    let mut debugger = Debugger::new(computer);
+    println!("{}", debugger.list(0, 1).join("\n"));
+
+    let stdin = io::stdin();
+    let mut last_command: Option<Command> = None;
+    loop {
+        print!("(intcode) ");
+        io::stdout().flush().unwrap();
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap() == 0 {
+            break;
+        }
+
+        // An empty line repeats the last command, which makes stepping easier.
+        let command = if line.trim().is_empty() {
+            match &last_command {
+                Some(command) => command.clone(),
+                None => continue,
+            }
+        } else {
+            match parse_command(&line) {
+                Ok(command) => command,
+                Err(e) => {
+                    println!("{}", e);
+                    continue;
+                }
+            }
+        };
+
+        if command == Command::Quit {
+            break;
+        }
+        println!("{}", debugger.run_command(&command));
+        last_command = Some(command);
+    }
+}
@@ -0,0 +1,21 @@
+use std::env;
+
+// With --dot, prints the control flow graph as Graphviz source instead, and
+// with --decompile, pseudocode.
+fn main() {
+    let input_path: &String = &env::args().nth(1).unwrap();
+    let p = intcode::read_from_path(input_path).unwrap();
+
+    if env::args().skip(2).any(|arg| arg == "--dot") {
+        print!("{}", intcode::cfg::analyze(&p).to_dot());
+        return;
+    }
+    if env::args().skip(2).any(|arg| arg == "--decompile") {
+        print!("{}", intcode::decompile::decompile(&p));
+        return;
+    }
+
+    for listing in intcode::disassemble(&p) {
+        println!("{}", listing);
+    }
+}
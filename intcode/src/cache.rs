@@ -0,0 +1,183 @@
+use crate::{Computer, InParam, Inst, Int, OutParam};
+
+// Decoded instructions by address. Anything that writes to memory has to call
+// invalidate, since a store into an instruction changes what it decodes to.
+#[derive(Clone, Debug)]
+pub(crate) struct DecodeCache<W> {
+    entries: Vec<Option<Inst<W>>>,
+}
+
+impl<W> Default for DecodeCache<W> {
+    fn default() -> Self {
+        DecodeCache{ entries: Vec::new() }
+    }
+}
+
+// The longest instruction, so the furthest back a write can land in one.
+const MAX_INST_LEN: usize = 4;
+
+impl<W: Int> DecodeCache<W> {
+    pub(crate) fn get(&self, pc: usize) -> Option<Inst<W>> {
+        self.entries.get(pc).cloned().flatten()
+    }
+
+    pub(crate) fn insert(&mut self, pc: usize, inst: Inst<W>) {
+        if pc >= self.entries.len() {
+            self.entries.resize(pc + 1, None);
+        }
+        self.entries[pc] = Some(inst);
+    }
+
+    // Forgets everything at or past len, for when mem shrinks.
+    pub(crate) fn truncate(&mut self, len: usize) {
+        self.entries.truncate(len);
+    }
+
+    pub(crate) fn invalidate(&mut self, address: usize) {
+        let start = address.saturating_sub(MAX_INST_LEN - 1);
+        let end = std::cmp::min(address.saturating_add(1), self.entries.len());
+        for pc in start..end {
+            // Most stores are to data, so check before writing.
+            if let Some(inst) = &self.entries[pc] {
+                if pc + inst.len() > address {
+                    self.entries[pc] = None;
+                }
+            }
+        }
+    }
+}
+
+impl<W: Int> Computer<W> {
+    // Remembers each instruction the first time it's decoded, which speeds up
+    // programs that loop. Writes made through the Computer keep it up to date,
+    // but after changing mem directly, call invalidate_decode_cache.
+    pub fn enable_decode_cache(&mut self) {
+        if self.decode_cache.is_none() {
+            self.decode_cache = Some(DecodeCache::default());
+        }
+    }
+
+    pub fn disable_decode_cache(&mut self) {
+        self.decode_cache = None;
+    }
+
+    // Also forgets that compiled code matched mem, see aot::can_run.
+    pub fn invalidate_decode_cache(&mut self) {
+        self.compiled_code = None;
+        if let Some(cache) = &mut self.decode_cache {
+            *cache = DecodeCache::default();
+        }
+    }
+
+    // Runs instructions straight out of the cache for as long as step
+    // wouldn't have anything else to do: no tracer, history, watchpoints or
+    // budget. Stops at the first instruction that isn't cached, or that would
+    // fail, halt, wait for input or grow memory, and leaves that one for step.
+    pub(crate) fn run_cached(&mut self) {
+        if self.tracer.is_some() || self.history.is_some() || !self.watchpoints.is_empty()
+            || self.fuel.is_some() || self.deadline.is_some() {
+            return;
+        }
+        let mut cache = match self.decode_cache.take() {
+            Some(cache) => cache,
+            None => return,
+        };
+        while let Some(Some(inst)) = cache.entries.get(self.pc) {
+            let stored = match self.run_simple(inst) {
+                Some(stored) => stored,
+                None => break,
+            };
+            self.instructions_executed += 1;
+            if let Some(address) = stored {
+                cache.invalidate(address);
+            }
+        }
+        self.decode_cache = Some(cache);
+    }
+
+    // Runs inst if it can't go wrong, and returns the address it stored to,
+    // if any. Nothing changes if it returns None.
+    fn run_simple(&mut self, inst: &Inst<W>) -> Option<Option<usize>> {
+        let mut next_pc = self.pc + inst.len();
+        let mut stored = None;
+        match inst {
+            Inst::Add(a, b, dst) => {
+                let value = self.overflow.add(self.pc, self.peek(a)?, self.peek(b)?).ok()?;
+                stored = Some(self.poke(dst, value)?);
+            }
+            Inst::Mult(a, b, dst) => {
+                let value = self.overflow.mul(self.pc, self.peek(a)?, self.peek(b)?).ok()?;
+                stored = Some(self.poke(dst, value)?);
+            }
+            Inst::LessThan(a, b, dst) => {
+                let value = W::from_i64((self.peek(a)? < self.peek(b)?) as i64);
+                stored = Some(self.poke(dst, value)?);
+            }
+            Inst::Equal(a, b, dst) => {
+                let value = W::from_i64((self.peek(a)? == self.peek(b)?) as i64);
+                stored = Some(self.poke(dst, value)?);
+            }
+            Inst::Input(dst) => {
+                let address = self.dense_address(dst)?;
+                let value = self.input.pop_front()?;
+                self.write_dense(address, value);
+                stored = Some(address);
+            }
+            Inst::Output(a) => {
+                let value = self.peek(a)?;
+                self.output.push_back(value);
+            }
+            Inst::JumpIfTrue(cond, target) => {
+                let (cond, target) = (self.peek(cond)?, self.peek(target)?.to_usize()?);
+                if !cond.is_zero() {
+                    next_pc = target;
+                }
+            }
+            Inst::JumpIfFalse(cond, target) => {
+                let (cond, target) = (self.peek(cond)?, self.peek(target)?.to_usize()?);
+                if cond.is_zero() {
+                    next_pc = target;
+                }
+            }
+            Inst::AdjustBase(a) => {
+                self.relative_base = Int::checked_add(&self.relative_base, &self.peek(a)?)?;
+            }
+            Inst::Exit => return None,
+        }
+        self.pc = next_pc;
+        Some(stored)
+    }
+
+    // A load from mem, but not from sparse memory or past the end.
+    fn peek(&self, param: &InParam<W>) -> Option<W> {
+        let address = match param {
+            InParam::Immediate(value) => return Some(value.clone()),
+            InParam::Position(address) => address.to_usize()?,
+            InParam::Relative(offset) => Int::checked_add(&self.relative_base, offset)?.to_usize()?,
+        };
+        self.mem.get(address).cloned()
+    }
+
+    fn dense_address(&self, param: &OutParam<W>) -> Option<usize> {
+        let address = match param {
+            OutParam::Position(address) => address.to_usize()?,
+            OutParam::Relative(offset) => Int::checked_add(&self.relative_base, offset)?.to_usize()?,
+        };
+        if address < self.mem.len() {
+            Some(address)
+        } else {
+            None
+        }
+    }
+
+    fn poke(&mut self, param: &OutParam<W>, value: W) -> Option<usize> {
+        let address = self.dense_address(param)?;
+        self.write_dense(address, value);
+        Some(address)
+    }
+
+    fn write_dense(&mut self, address: usize, value: W) {
+        self.compiled_code = None;
+        self.mem[address] = value;
+    }
+}
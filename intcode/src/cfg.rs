@@ -0,0 +1,169 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+use crate::disasm::{jump_target, label_for, reachable, successors};
+use crate::{InParam, Inst, OutParam, Word};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Block {
+    pub start: usize,
+    pub insts: Vec<(usize, Inst)>,
+    // Starts of the blocks control can go to next, as far as can be told
+    // statically.
+    pub successors: Vec<usize>,
+    // Ends in a jump whose target comes from memory, so there may be
+    // successors that aren't listed.
+    pub indirect: bool,
+}
+
+impl Block {
+    pub fn last(&self) -> (usize, Inst) {
+        self.insts[self.insts.len() - 1]
+    }
+
+    // One past the last word of the block.
+    pub fn end(&self) -> usize {
+        let (pc, inst) = self.last();
+        pc + inst.len()
+    }
+
+    pub fn halts(&self) -> bool {
+        self.last().1 == Inst::Exit
+    }
+}
+
+// A store to a fixed address that holds part of a reachable instruction.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CodeStore {
+    pub pc: usize,
+    pub address: usize,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Cfg {
+    pub blocks: BTreeMap<usize, Block>,
+    // Stores through the relative base can't be resolved statically, so they
+    // never show up here.
+    pub code_stores: Vec<CodeStore>,
+}
+
+// Splits the code reachable from pc 0 into basic blocks. A block starts at 0,
+// at a jump target, or right after a jump, and ends at a jump, a halt, or
+// just before another block starts.
+pub fn analyze(p: &[Word]) -> Cfg {
+    let code = reachable(p);
+
+    let mut leaders = BTreeSet::new();
+    if code.contains_key(&0) {
+        leaders.insert(0);
+    }
+    for (&pc, inst) in code.iter() {
+        if is_jump(inst) {
+            leaders.extend(successors(inst, pc));
+        }
+    }
+
+    let mut blocks = BTreeMap::new();
+    for &start in leaders.iter().filter(|pc| code.contains_key(pc)) {
+        let mut insts = Vec::new();
+        let mut pc = start;
+        while let Some(&inst) = code.get(&pc) {
+            insts.push((pc, inst));
+            pc += inst.len();
+            if is_jump(&inst) || inst == Inst::Exit || leaders.contains(&pc) {
+                break;
+            }
+        }
+
+        let (last_pc, last) = insts[insts.len() - 1];
+        let successors = successors(&last, last_pc).into_iter().filter(|s| code.contains_key(s)).collect();
+        let indirect = matches!(last,
+            Inst::JumpIfTrue(_, target) | Inst::JumpIfFalse(_, target) if !matches!(target, InParam::Immediate(_)));
+        blocks.insert(start, Block { start, insts, successors, indirect });
+    }
+
+    let code_words: BTreeSet<usize> = code.iter().flat_map(|(&pc, inst)| pc..pc + inst.len()).collect();
+    let code_stores = code.iter()
+        .filter_map(|(&pc, inst)| match store_address(inst) {
+            Some(address) if code_words.contains(&address) => Some(CodeStore { pc, address }),
+            _ => None,
+        })
+        .collect();
+
+    Cfg { blocks, code_stores }
+}
+
+fn is_jump(inst: &Inst) -> bool {
+    matches!(inst, Inst::JumpIfTrue(_, _) | Inst::JumpIfFalse(_, _))
+}
+
+fn store_address(inst: &Inst) -> Option<usize> {
+    let dst = match *inst {
+        Inst::Add(_, _, dst) | Inst::Mult(_, _, dst) | Inst::LessThan(_, _, dst) | Inst::Equal(_, _, dst) => dst,
+        Inst::Input(dst) => dst,
+        _ => return None,
+    };
+    match dst {
+        OutParam::Position(address) => usize::try_from(address).ok(),
+        OutParam::Relative(_) => None,
+    }
+}
+
+impl Cfg {
+    pub fn block_containing(&self, pc: usize) -> Option<&Block> {
+        self.blocks.values().find(|b| b.insts.iter().any(|&(start, inst)| start <= pc && pc < start + inst.len()))
+    }
+
+    pub fn indirect_jumps(&self) -> impl Iterator<Item = usize> + '_ {
+        self.blocks.values().filter(|b| b.indirect).map(|b| b.last().0)
+    }
+
+    // Graphviz source. Blocks that halt have a double border, blocks with
+    // stores into code are red, and indirect jumps get a dashed edge to "?".
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+        // Writing to a String can't fail.
+        let _ = self.write_dot(&mut out);
+        out
+    }
+
+    fn write_dot(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(out, "digraph cfg {{")?;
+        writeln!(out, "    node [shape=box, fontname=\"monospace\"];")?;
+
+        for block in self.blocks.values() {
+            let mut label = format!("{}:\\l", label_for(block.start));
+            for (pc, inst) in block.insts.iter() {
+                write!(label, "{:>6}  {}\\l", pc, inst)?;
+            }
+            let mut attrs = format!("label=\"{}\"", label);
+            if block.halts() {
+                attrs.push_str(", peripheries=2");
+            }
+            if self.code_stores.iter().any(|s| block.insts.iter().any(|&(pc, _)| pc == s.pc)) {
+                attrs.push_str(", color=red");
+            }
+            writeln!(out, "    {} [{}];", label_for(block.start), attrs)?;
+        }
+
+        let mut any_indirect = false;
+        for block in self.blocks.values() {
+            let (_, last) = block.last();
+            let target = jump_target(&last);
+            for &s in block.successors.iter() {
+                let style = if Some(s) == target && block.successors.len() > 1 { " [label=\"jump\"]" } else { "" };
+                writeln!(out, "    {} -> {}{};", label_for(block.start), label_for(s), style)?;
+            }
+            if block.indirect {
+                any_indirect = true;
+                writeln!(out, "    {} -> indirect [style=dashed];", label_for(block.start))?;
+            }
+        }
+        if any_indirect {
+            writeln!(out, "    indirect [label=\"?\", shape=circle];")?;
+        }
+
+        writeln!(out, "}}")
+    }
+}
@@ -0,0 +1,512 @@
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::{decode, Computer, Error, Inst, WatchAction, WatchEvent, WatchKind, Word};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "==" => Some(Comparison::Eq),
+            "!=" => Some(Comparison::Ne),
+            "<" => Some(Comparison::Lt),
+            "<=" => Some(Comparison::Le),
+            ">" => Some(Comparison::Gt),
+            ">=" => Some(Comparison::Ge),
+            _ => None,
+        }
+    }
+
+    fn apply(self, a: Word, b: Word) -> bool {
+        match self {
+            Comparison::Eq => a == b,
+            Comparison::Ne => a != b,
+            Comparison::Lt => a < b,
+            Comparison::Le => a <= b,
+            Comparison::Gt => a > b,
+            Comparison::Ge => a >= b,
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", match self {
+            Comparison::Eq => "==",
+            Comparison::Ne => "!=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        })
+    }
+}
+
+// A condition on a memory cell, written as "[address] op value".
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Condition {
+    pub address: usize,
+    pub comparison: Comparison,
+    pub value: Word,
+}
+
+impl Condition {
+    fn holds(&self, c: &Computer) -> bool {
+        self.comparison.apply(c.read_mem(self.address), self.value)
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {} {}", self.address, self.comparison, self.value)
+    }
+}
+
+// A breakpoint with no pc fires whenever its condition becomes true, so it
+// has to remember whether the condition held last time it was checked.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Breakpoint {
+    pub pc: Option<usize>,
+    pub condition: Option<Condition>,
+    held: bool,
+}
+
+impl Breakpoint {
+    pub fn new(pc: Option<usize>, condition: Option<Condition>) -> Self {
+        Self { pc, condition, held: false }
+    }
+
+    fn hit(&mut self, c: &Computer) -> bool {
+        let holds = self.condition.is_none_or(|cond| cond.holds(c));
+        match (self.pc, self.condition) {
+            (Some(pc), _) => pc == c.pc && holds,
+            (None, Some(_)) => {
+                let became_true = holds && !self.held;
+                self.held = holds;
+                became_true
+            }
+            (None, None) => true,
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.pc, self.condition) {
+            (Some(pc), Some(cond)) => write!(f, "pc {} if {}", pc, cond),
+            (Some(pc), None) => write!(f, "pc {}", pc),
+            (None, Some(cond)) => write!(f, "when {}", cond),
+            (None, None) => write!(f, "always"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Stop {
+    Stepped,
+    Breakpoint(usize),
+    Halted,
+    InputNeeded,
+    Watchpoint(WatchEvent),
+    Failed(Error),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Location {
+    Pc,
+    RelativeBase,
+    Mem(usize, usize),
+    Input,
+    Output,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Command {
+    Break(Breakpoint),
+    Delete(usize),
+    Watch(usize, WatchKind),
+    Breakpoints,
+    Step(usize),
+    Back(usize),
+    Next,
+    Continue,
+    Print(Location),
+    Set(Location, Word),
+    Input(Vec<Word>),
+    List(Option<usize>, usize),
+    Help,
+    Quit,
+}
+
+pub const HELP: &str = "\
+break <pc> [if [addr] <op> <value>]   stop at pc, optionally only when the condition holds
+break if [addr] <op> <value>          stop as soon as the condition holds
+delete <n>                            remove breakpoint n
+watch [addr] [read|write|rw]          stop when memory is accessed (default write)
+info                                  list breakpoints
+step [n]                              execute n instructions (default 1)
+back [n]                              undo n instructions (default 1)
+next                                  run until the instruction after this one
+continue                              run until a breakpoint, halt, or input is needed
+print pc|rb|[addr]|[addr..end]|input|output
+set pc|rb|[addr] <value>
+input <value>...                      queue input values
+list [addr] [n]                       show n instructions starting at addr (default pc)
+quit";
+
+pub fn parse_command(line: &str) -> Result<Command, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let (name, args) = match words.split_first() {
+        Some((name, args)) => (*name, args),
+        None => return Err("empty command".to_string()),
+    };
+
+    match name {
+        "b" | "break" => parse_breakpoint(args).map(Command::Break),
+        "d" | "delete" => match args {
+            [n] => Ok(Command::Delete(parse_number(n)?)),
+            _ => Err("usage: delete <n>".to_string()),
+        },
+        "w" | "watch" => {
+            let address = match args.first().map(|a| parse_location(a)) {
+                Some(Ok(Location::Mem(address, end))) if address.checked_add(1) == Some(end) => address,
+                _ => return Err("usage: watch [addr] [read|write|rw]".to_string()),
+            };
+            let kind = match args.get(1) {
+                None | Some(&"write") => WatchKind::Write,
+                Some(&"read") => WatchKind::Read,
+                Some(&"rw") => WatchKind::ReadWrite,
+                Some(kind) => return Err(format!("bad watch kind {:?}", kind)),
+            };
+            Ok(Command::Watch(address, kind))
+        }
+        "i" | "info" => Ok(Command::Breakpoints),
+        "s" | "step" => match args {
+            [] => Ok(Command::Step(1)),
+            [n] => Ok(Command::Step(parse_number(n)?)),
+            _ => Err("usage: step [n]".to_string()),
+        },
+        "back" => match args {
+            [] => Ok(Command::Back(1)),
+            [n] => Ok(Command::Back(parse_number(n)?)),
+            _ => Err("usage: back [n]".to_string()),
+        },
+        "n" | "next" => Ok(Command::Next),
+        "c" | "continue" => Ok(Command::Continue),
+        "p" | "print" => match args {
+            [loc] => Ok(Command::Print(parse_location(loc)?)),
+            _ => Err("usage: print <location>".to_string()),
+        },
+        "set" => match args {
+            [loc, value] => Ok(Command::Set(parse_location(loc)?, parse_number(value)?)),
+            _ => Err("usage: set <location> <value>".to_string()),
+        },
+        "in" | "input" => args.iter()
+            .map(|a| parse_number(a))
+            .collect::<Result<Vec<Word>, String>>()
+            .map(Command::Input),
+        "l" | "list" => match args {
+            [] => Ok(Command::List(None, 5)),
+            [addr] => Ok(Command::List(Some(parse_number(addr)?), 5)),
+            [addr, n] => Ok(Command::List(Some(parse_number(addr)?), parse_number(n)?)),
+            _ => Err("usage: list [addr] [n]".to_string()),
+        },
+        "h" | "help" => Ok(Command::Help),
+        "q" | "quit" => Ok(Command::Quit),
+        _ => Err(format!("unknown command {:?}, try help", name)),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(s: &str) -> Result<T, String> {
+    s.parse::<T>().map_err(|_| format!("bad number {:?}", s))
+}
+
+fn parse_breakpoint(args: &[&str]) -> Result<Breakpoint, String> {
+    let (pc, rest) = match args {
+        [] => return Err("usage: break <pc> [if <condition>]".to_string()),
+        ["if", rest @ ..] => (None, rest),
+        [pc, "if", rest @ ..] => (Some(parse_number(pc)?), rest),
+        [pc] => return Ok(Breakpoint::new(Some(parse_number(pc)?), None)),
+        _ => return Err("usage: break <pc> [if <condition>]".to_string()),
+    };
+
+    let condition = match rest {
+        [cell, op, value] => {
+            let address = match parse_location(cell)? {
+                Location::Mem(address, end) if address.checked_add(1) == Some(end) => address,
+                _ => return Err(format!("conditions must test one memory cell, not {:?}", cell)),
+            };
+            let comparison = Comparison::parse(op).ok_or_else(|| format!("bad comparison {:?}", op))?;
+            Condition { address, comparison, value: parse_number(value)? }
+        }
+        _ => return Err("conditions look like [addr] == value".to_string()),
+    };
+
+    Ok(Breakpoint::new(pc, Some(condition)))
+}
+
+// The most cells print and set will take at once.
+const MAX_RANGE: usize = 4096;
+
+fn parse_location(s: &str) -> Result<Location, String> {
+    match s {
+        "pc" => Ok(Location::Pc),
+        "rb" => Ok(Location::RelativeBase),
+        "input" => Ok(Location::Input),
+        "output" => Ok(Location::Output),
+        _ => {
+            let inner = s.strip_prefix('[').and_then(|s| s.strip_suffix(']'))
+                .ok_or_else(|| format!("bad location {:?}", s))?;
+            match inner.find("..") {
+                Some(i) => {
+                    let (start, end): (usize, usize) = (parse_number(&inner[..i])?, parse_number(&inner[i + 2..])?);
+                    if end.saturating_sub(start) > MAX_RANGE {
+                        return Err(format!("ranges can be at most {} cells", MAX_RANGE));
+                    }
+                    Ok(Location::Mem(start, end))
+                }
+                None => {
+                    let address: usize = parse_number(inner)?;
+                    let end = address.checked_add(1).ok_or_else(|| format!("bad address {:?}", inner))?;
+                    Ok(Location::Mem(address, end))
+                }
+            }
+        }
+    }
+}
+
+// Goes through read_mem rather than c.mem, so that code in sparse memory can
+// be decoded too.
+fn decode_at(c: &Computer, pc: usize) -> Result<Inst, Error> {
+    let words: Vec<Word> = (pc..pc.saturating_add(4)).map(|a| c.read_mem(a)).collect();
+    decode(&words, 0).map_err(|e| e.with_pc(pc))
+}
+
+// How many instructions the debugger can step back through.
+const HISTORY_LIMIT: usize = 100_000;
+
+pub struct Debugger {
+    pub computer: Computer,
+    pub breakpoints: Vec<Option<Breakpoint>>,
+}
+
+impl Debugger {
+    pub fn new(mut computer: Computer) -> Self {
+        computer.enable_history(Some(HISTORY_LIMIT));
+        Self { computer, breakpoints: Vec::new() }
+    }
+
+    // A condition that already holds doesn't count as becoming true.
+    pub fn add_breakpoint(&mut self, mut breakpoint: Breakpoint) -> usize {
+        breakpoint.hit(&self.computer);
+        self.breakpoints.push(Some(breakpoint));
+        self.breakpoints.len() - 1
+    }
+
+    pub fn remove_breakpoint(&mut self, n: usize) -> bool {
+        match self.breakpoints.get_mut(n) {
+            Some(b) => b.take().is_some(),
+            None => false,
+        }
+    }
+
+    // Checks every breakpoint, even after finding one that's hit, so that
+    // they all see each change to their condition.
+    fn breakpoint_hit(&mut self) -> Option<usize> {
+        let mut first = None;
+        for (n, b) in self.breakpoints.iter_mut().enumerate() {
+            if let Some(b) = b {
+                if b.hit(&self.computer) && first.is_none() {
+                    first = Some(n);
+                }
+            }
+        }
+        first
+    }
+
+    fn step_once(&mut self) -> Option<Stop> {
+        match self.computer.step() {
+            Err(e) => Some(Stop::Failed(e)),
+            Ok(result) if result.done => Some(Stop::Halted),
+            Ok(result) if result.input_needed => Some(Stop::InputNeeded),
+            Ok(result) if result.paused => Some(Stop::Watchpoint(result.watch_hits[0])),
+            Ok(_) => None,
+        }
+    }
+
+    pub fn step(&mut self, n: usize) -> Stop {
+        for _ in 0..n {
+            if let Some(stop) = self.step_once() {
+                return stop;
+            }
+            self.breakpoint_hit();
+        }
+        Stop::Stepped
+    }
+
+    // Runs until a breakpoint is hit. The instruction at the current pc always
+    // runs, so continuing from a breakpoint doesn't stop on it again.
+    pub fn cont(&mut self) -> Stop {
+        loop {
+            if let Some(stop) = self.step_once() {
+                return stop;
+            }
+            if let Some(n) = self.breakpoint_hit() {
+                return Stop::Breakpoint(n);
+            }
+        }
+    }
+
+    // Like step, but runs over jumps that come back to the next instruction,
+    // such as calls into subroutines.
+    pub fn step_over(&mut self) -> Stop {
+        let after = match decode_at(&self.computer, self.computer.pc) {
+            Ok(inst) => self.computer.pc + inst.len(),
+            Err(e) => return Stop::Failed(e),
+        };
+        loop {
+            if let Some(stop) = self.step_once() {
+                return stop;
+            }
+            if self.computer.pc == after {
+                return Stop::Stepped;
+            }
+            if let Some(n) = self.breakpoint_hit() {
+                return Stop::Breakpoint(n);
+            }
+        }
+    }
+
+    pub fn list(&self, start: usize, count: usize) -> Vec<String> {
+        let mut result = Vec::new();
+        let mut pc = start;
+        for _ in 0..count {
+            let marker = if pc == self.computer.pc { "=>" } else { "  " };
+            match decode_at(&self.computer, pc) {
+                Ok(inst) => {
+                    result.push(format!("{} {:>6}: {}", marker, pc, inst));
+                    pc += inst.len();
+                }
+                Err(_) => {
+                    result.push(format!("{} {:>6}: .data {}", marker, pc, self.computer.read_mem(pc)));
+                    pc += 1;
+                }
+            }
+            if pc >= self.computer.mem.len() && self.computer.sparse_memory_start().is_none() {
+                break;
+            }
+        }
+        result
+    }
+
+    fn describe_stop(&mut self, stop: Stop) -> String {
+        let mut lines = Vec::new();
+        for value in self.computer.take_output() {
+            lines.push(format!("output: {}", value));
+        }
+        match stop {
+            Stop::Stepped => {}
+            Stop::Breakpoint(n) => lines.push(format!("breakpoint {} hit", n)),
+            Stop::Halted => lines.push("program halted".to_string()),
+            Stop::InputNeeded => lines.push("waiting for input".to_string()),
+            Stop::Watchpoint(e) => lines.push(format!("{:?} of [{}] at pc {}: {} -> {}",
+                e.access, e.address, e.pc, e.old_value, e.new_value)),
+            Stop::Failed(e) => lines.push(format!("error: {}", e)),
+        }
+        lines.extend(self.list(self.computer.pc, 1));
+        lines.join("\n")
+    }
+
+    pub fn run_command(&mut self, command: &Command) -> String {
+        match command {
+            Command::Break(b) => {
+                let n = self.add_breakpoint(*b);
+                format!("breakpoint {}: {}", n, b)
+            }
+            Command::Delete(n) => {
+                if self.remove_breakpoint(*n) {
+                    format!("deleted breakpoint {}", n)
+                } else {
+                    format!("no breakpoint {}", n)
+                }
+            }
+            Command::Watch(address, kind) => {
+                let n = self.computer.watch(*address, *kind, WatchAction::Pause);
+                format!("watchpoint {}: {:?} of [{}]", n, kind, address)
+            }
+            Command::Breakpoints => {
+                let lines: Vec<String> = self.breakpoints.iter().enumerate()
+                    .filter_map(|(n, b)| b.map(|b| format!("{}: {}", n, b)))
+                    .collect();
+                if lines.is_empty() {
+                    "no breakpoints".to_string()
+                } else {
+                    lines.join("\n")
+                }
+            }
+            Command::Step(n) => {
+                let stop = self.step(*n);
+                self.describe_stop(stop)
+            }
+            Command::Back(n) => {
+                let undone = self.computer.rewind(*n);
+                let mut lines = vec!(format!("stepped back {} instructions", undone));
+                lines.extend(self.list(self.computer.pc, 1));
+                lines.join("\n")
+            }
+            Command::Next => {
+                let stop = self.step_over();
+                self.describe_stop(stop)
+            }
+            Command::Continue => {
+                let stop = self.cont();
+                self.describe_stop(stop)
+            }
+            Command::Print(loc) => match loc {
+                Location::Pc => format!("pc = {}", self.computer.pc),
+                Location::RelativeBase => format!("rb = {}", self.computer.relative_base),
+                Location::Mem(start, end) => (*start..*end)
+                    .map(|a| format!("[{}] = {}", a, self.computer.read_mem(a)))
+                    .collect::<Vec<String>>()
+                    .join("\n"),
+                Location::Input => format!("input = {:?}", self.computer.input),
+                Location::Output => format!("output = {:?}", self.computer.output),
+            },
+            Command::Set(loc, value) => match loc {
+                Location::Pc if usize::try_from(*value).is_ok() => {
+                    self.computer.pc = *value as usize;
+                    format!("pc = {}", value)
+                }
+                Location::RelativeBase => {
+                    self.computer.relative_base = *value;
+                    format!("rb = {}", value)
+                }
+                Location::Mem(start, end) => {
+                    for a in *start..*end {
+                        if let Err(e) = self.computer.write_mem(a, *value) {
+                            return e.to_string();
+                        }
+                    }
+                    format!("[{}..{}] = {}", start, end, value)
+                }
+                _ => format!("can't set {:?} to {}", loc, value),
+            },
+            Command::Input(values) => {
+                self.computer.input.extend(values.iter());
+                format!("input = {:?}", self.computer.input)
+            }
+            Command::List(start, n) => self.list(start.unwrap_or(self.computer.pc), *n).join("\n"),
+            Command::Help => HELP.to_string(),
+            Command::Quit => String::new(),
+        }
+    }
+}
@@ -0,0 +1,624 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::convert::TryFrom;
+use std::fmt::{self, Write};
+
+use crate::disasm::{jump_target, successors};
+use crate::{decode, InParam, Inst, OutParam, Word};
+
+// How many instructions can come between pushing a return address and the
+// jump for it to still count as a call. They're usually argument stores.
+const MAX_CALL_SETUP: usize = 16;
+
+// Turns a program into pseudocode, recognizing what the puzzle compiler does:
+//
+//   call: store an immediate return address through the relative base, maybe
+//         store some arguments after it, then jump unconditionally. The
+//         return address is the one just past the jump.
+//   frame: a function that starts with ARB n (n > 0) has a frame of n words
+//          below its relative base, holding the return address, arguments
+//          and locals.
+//   return: ARB -n followed by an unconditional jump through the relative
+//           base.
+//
+// Backward jumps become loops and forward branches over a stretch of code
+// become ifs, as long as nothing else jumps into the middle. Anything else is
+// left as a goto.
+pub fn decompile(p: &[Word]) -> String {
+    let program = Program::discover(p);
+    let mut out = String::new();
+    for (i, &entry) in program.functions.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        // Writing to a String can't fail.
+        let _ = program.function(entry).write(&mut out);
+    }
+    out
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Call {
+    // The store that pushed the return address.
+    store: usize,
+    target: usize,
+    ret: usize,
+    // The relative offset the return address was stored at.
+    slot: Word,
+}
+
+fn find_call(p: &[Word], pc: usize, inst: &Inst) -> Option<(usize, Call)> {
+    let (value, slot) = match *inst {
+        Inst::Add(InParam::Immediate(a), InParam::Immediate(b), OutParam::Relative(slot)) => (a.checked_add(b)?, slot),
+        Inst::Mult(InParam::Immediate(a), InParam::Immediate(b), OutParam::Relative(slot)) => (a.checked_mul(b)?, slot),
+        _ => return None,
+    };
+    let ret = usize::try_from(value).ok()?;
+
+    let mut at = pc + inst.len();
+    for _ in 0..MAX_CALL_SETUP {
+        let next = decode(p, at).ok()?;
+        match next {
+            Inst::JumpIfTrue(InParam::Immediate(c), InParam::Immediate(_)) if c != 0 => {}
+            Inst::JumpIfFalse(InParam::Immediate(0), InParam::Immediate(_)) => {}
+            Inst::JumpIfTrue(_, _) | Inst::JumpIfFalse(_, _) | Inst::Exit => return None,
+            _ => {
+                at += next.len();
+                continue;
+            }
+        }
+        let target = jump_target(&next)?;
+        return if at + next.len() == ret { Some((at, Call { store: pc, target, ret, slot })) } else { None };
+    }
+    None
+}
+
+// An unconditional jump through the relative base.
+fn is_return(inst: &Inst) -> bool {
+    matches!(inst,
+        Inst::JumpIfTrue(InParam::Immediate(c), InParam::Relative(_)) if *c != 0)
+        || matches!(inst, Inst::JumpIfFalse(InParam::Immediate(0), InParam::Relative(_)))
+}
+
+fn inputs(inst: &Inst) -> Vec<InParam> {
+    match *inst {
+        Inst::Add(a, b, _) | Inst::Mult(a, b, _) | Inst::LessThan(a, b, _) | Inst::Equal(a, b, _) => vec!(a, b),
+        Inst::JumpIfTrue(a, b) | Inst::JumpIfFalse(a, b) => vec!(a, b),
+        Inst::Output(a) | Inst::AdjustBase(a) => vec!(a),
+        Inst::Input(_) | Inst::Exit => vec!(),
+    }
+}
+
+fn output(inst: &Inst) -> Option<OutParam> {
+    match *inst {
+        Inst::Add(_, _, dst) | Inst::Mult(_, _, dst) | Inst::LessThan(_, _, dst) | Inst::Equal(_, _, dst) => Some(dst),
+        Inst::Input(dst) => Some(dst),
+        _ => None,
+    }
+}
+
+fn same_location(dst: OutParam, src: InParam) -> bool {
+    match (dst, src) {
+        (OutParam::Position(a), InParam::Position(b)) | (OutParam::Relative(a), InParam::Relative(b)) => a == b,
+        _ => false,
+    }
+}
+
+struct Program {
+    insts: BTreeMap<usize, Inst>,
+    calls: BTreeMap<usize, Call>,
+    functions: BTreeSet<usize>,
+    // Every immediate jump target, so that idioms spanning several
+    // instructions aren't folded when something can jump into the middle.
+    targets: BTreeSet<usize>,
+    // Argument stores at each call, by the pc of the call's jump.
+    args: BTreeMap<usize, BTreeMap<Word, usize>>,
+    frames: BTreeMap<usize, Frame>,
+}
+
+impl Program {
+    // Like disasm::reachable, but a call also continues at its return
+    // address, which is only ever reached through memory. Finding a call can
+    // make more code reachable, which can have more calls in it.
+    fn discover(p: &[Word]) -> Self {
+        let mut insts = BTreeMap::new();
+        let mut calls = BTreeMap::new();
+        let mut functions = BTreeSet::new();
+        functions.insert(0);
+
+        loop {
+            let known = calls.len();
+            let mut pending: Vec<usize> = functions.iter().copied()
+                .chain(calls.values().map(|c: &Call| c.ret))
+                .collect();
+            while let Some(pc) = pending.pop() {
+                if insts.contains_key(&pc) {
+                    continue;
+                }
+                let inst = match decode(p, pc) {
+                    Ok(inst) => inst,
+                    Err(_) => continue,
+                };
+                insts.insert(pc, inst);
+                if let Some((jump, call)) = find_call(p, pc, &inst) {
+                    calls.insert(jump, call);
+                    functions.insert(call.target);
+                    pending.push(call.target);
+                    pending.push(call.ret);
+                }
+                if !calls.contains_key(&pc) {
+                    pending.extend(successors(&inst, pc));
+                }
+            }
+            if calls.len() == known {
+                break;
+            }
+            insts.clear();
+        }
+        // A call's jump was followed as an ordinary jump if it was decoded
+        // before the call was found, so its target is a function, not a label.
+        let targets = insts.iter()
+            .filter(|(pc, _)| !calls.contains_key(pc))
+            .filter_map(|(_, inst)| jump_target(inst))
+            .collect();
+
+        let mut program = Program { insts, calls, functions, targets, args: BTreeMap::new(), frames: BTreeMap::new() };
+        for (&jump, &call) in program.calls.iter() {
+            program.args.insert(jump, program.call_args(jump, call));
+        }
+        for &entry in program.functions.iter() {
+            let size = match program.insts.get(&entry) {
+                Some(Inst::AdjustBase(InParam::Immediate(n))) if *n > 0 => *n,
+                _ => 0,
+            };
+            let sites: Vec<usize> = program.calls.iter().filter(|(_, c)| c.target == entry).map(|(&j, _)| j).collect();
+            let ret_slot = sites.first().map_or(0, |j| program.calls[j].slot);
+            let args = sites.iter().filter_map(|j| program.args[j].keys().next_back()).copied().max().unwrap_or(0);
+            program.frames.insert(entry, Frame { size, ret_slot, args });
+        }
+        program
+    }
+
+    fn previous(&self, pc: usize) -> Option<(usize, Inst)> {
+        let (&prev, &inst) = self.insts.range(..pc).next_back()?;
+        if prev + inst.len() == pc && !self.targets.contains(&pc) { Some((prev, inst)) } else { None }
+    }
+
+    // The stores to the slots just above the return address that come
+    // straight before a call, by argument number (starting at 1).
+    fn call_args(&self, jump: usize, call: Call) -> BTreeMap<Word, usize> {
+        let mut args = BTreeMap::new();
+        let mut pc = jump;
+        while let Some((prev, inst)) = self.previous(pc) {
+            match output(&inst) {
+                Some(OutParam::Relative(offset)) if offset == call.slot => {}
+                Some(OutParam::Relative(offset)) if offset > call.slot && !args.contains_key(&(offset - call.slot)) => {
+                    args.insert(offset - call.slot, prev);
+                }
+                _ => break,
+            }
+            pc = prev;
+        }
+        args
+    }
+
+    fn function(&self, entry: usize) -> Function {
+        let mut insts = BTreeMap::new();
+        let mut pending = vec!(entry);
+        while let Some(pc) = pending.pop() {
+            if insts.contains_key(&pc) || (pc != entry && self.functions.contains(&pc)) {
+                continue;
+            }
+            let inst = match self.insts.get(&pc) {
+                Some(&inst) => inst,
+                None => continue,
+            };
+            insts.insert(pc, inst);
+            match self.calls.get(&pc) {
+                Some(call) => pending.push(call.ret),
+                None if is_return(&inst) => {}
+                None => pending.extend(successors(&inst, pc)),
+            }
+        }
+        Function::new(self, entry, insts)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Frame {
+    size: Word,
+    ret_slot: Word,
+    args: Word,
+}
+
+impl Frame {
+    fn slot(&self, offset: Word) -> String {
+        if self.size > 0 && (-self.size..0).contains(&offset) {
+            let i = offset + self.size;
+            let arg = i - self.ret_slot;
+            if (1..=self.args).contains(&arg) {
+                return format!("arg{}", arg);
+            }
+            return format!("local{}", i);
+        }
+        format!("rb[{}]", offset)
+    }
+
+    fn read(&self, param: InParam) -> String {
+        match param {
+            InParam::Immediate(v) => v.to_string(),
+            InParam::Position(a) => format!("mem[{}]", a),
+            InParam::Relative(o) => self.slot(o),
+        }
+    }
+
+    fn write(&self, param: OutParam) -> String {
+        match param {
+            OutParam::Position(a) => format!("mem[{}]", a),
+            OutParam::Relative(o) => self.slot(o),
+        }
+    }
+
+    fn value(&self, inst: &Inst) -> String {
+        match *inst {
+            Inst::Add(a, InParam::Immediate(0), _) | Inst::Add(InParam::Immediate(0), a, _) => self.read(a),
+            Inst::Mult(a, InParam::Immediate(1), _) | Inst::Mult(InParam::Immediate(1), a, _) => self.read(a),
+            Inst::Add(a, InParam::Immediate(b), _) if b < 0 => format!("{} - {}", self.read(a), b.unsigned_abs()),
+            Inst::Add(a, b, _) => format!("{} + {}", self.read(a), self.read(b)),
+            Inst::Mult(a, InParam::Immediate(-1), _) | Inst::Mult(InParam::Immediate(-1), a, _) => format!("-{}", self.read(a)),
+            Inst::Mult(a, b, _) => format!("{} * {}", self.read(a), self.read(b)),
+            Inst::LessThan(a, b, _) => format!("{} < {}", self.read(a), self.read(b)),
+            Inst::Equal(a, b, _) => format!("{} == {}", self.read(a), self.read(b)),
+            Inst::Input(_) => "input()".to_string(),
+            _ => String::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Cond {
+    lhs: String,
+    op: &'static str,
+    rhs: String,
+}
+
+impl Cond {
+    fn negate(&self) -> Cond {
+        let op = match self.op {
+            "==" => "!=",
+            "!=" => "==",
+            "<" => ">=",
+            _ => "<",
+        };
+        Cond { lhs: self.lhs.clone(), op, rhs: self.rhs.clone() }
+    }
+}
+
+impl fmt::Display for Cond {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.lhs, self.op, self.rhs)
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Kind {
+    // Folded into something else, like a prologue or an argument store.
+    Skip,
+    Stmt(String),
+    Branch { target: usize, cond: Cond },
+    Goto(usize),
+}
+
+#[derive(Clone, Debug)]
+struct Item {
+    pc: usize,
+    end: usize,
+    kind: Kind,
+}
+
+impl Item {
+    fn target(&self) -> Option<usize> {
+        match self.kind {
+            Kind::Branch { target, .. } | Kind::Goto(target) => Some(target),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Stmt(usize, String),
+    Goto(usize, usize),
+    Break(usize),
+    Continue(usize),
+    If { pc: usize, cond: Cond, then: Vec<Node>, els: Vec<Node> },
+    // A loop with a condition is a do-while.
+    Loop { pc: usize, body: Vec<Node>, cond: Option<Cond> },
+}
+
+impl Node {
+    fn pc(&self) -> usize {
+        match *self {
+            Node::Stmt(pc, _) | Node::Goto(pc, _) | Node::Break(pc) | Node::Continue(pc) => pc,
+            Node::If { pc, .. } | Node::Loop { pc, .. } => pc,
+        }
+    }
+}
+
+fn has_continue(nodes: &[Node]) -> bool {
+    nodes.iter().any(|n| match n {
+        Node::Continue(_) => true,
+        Node::If { then, els, .. } => has_continue(then) || has_continue(els),
+        _ => false,
+    })
+}
+
+fn gotos(nodes: &[Node], labels: &mut BTreeSet<usize>) {
+    for node in nodes {
+        match node {
+            Node::Goto(_, target) => {
+                labels.insert(*target);
+            }
+            Node::If { then, els, .. } => {
+                gotos(then, labels);
+                gotos(els, labels);
+            }
+            Node::Loop { body, .. } => gotos(body, labels),
+            _ => {}
+        }
+    }
+}
+
+// Where jumps to a loop's start and to just past its end go.
+#[derive(Clone, Copy, Debug, Default)]
+struct Context {
+    header: Option<usize>,
+    exit: Option<usize>,
+}
+
+struct Function {
+    entry: usize,
+    frame: Frame,
+    items: Vec<Item>,
+    index: HashMap<usize, usize>,
+}
+
+impl Function {
+    fn new(program: &Program, entry: usize, insts: BTreeMap<usize, Inst>) -> Self {
+        let frame = program.frames.get(&entry).copied().unwrap_or_default();
+        let mut skip = BTreeSet::new();
+        let mut conds = HashMap::new();
+
+        if frame.size > 0 && !program.targets.contains(&entry) {
+            skip.insert(entry);
+        }
+        for (&pc, inst) in insts.iter() {
+            let prev = program.previous(pc).filter(|(prev, _)| insts.contains_key(prev));
+            if let Some(call) = program.calls.get(&pc) {
+                skip.extend(program.args[&pc].values());
+                if !program.targets.contains(&call.store) {
+                    skip.insert(call.store);
+                }
+            } else if is_return(inst) {
+                if let Some((prev, Inst::AdjustBase(InParam::Immediate(n)))) = prev {
+                    if n == -frame.size {
+                        skip.insert(prev);
+                    }
+                }
+            } else if let Inst::JumpIfTrue(c, _) | Inst::JumpIfFalse(c, _) = *inst {
+                // A comparison into a temporary that's only read by the jump
+                // right after it becomes the jump's condition.
+                let (prev, compare) = match prev {
+                    Some((prev, compare @ (Inst::LessThan(_, _, _) | Inst::Equal(_, _, _)))) => (prev, compare),
+                    _ => continue,
+                };
+                let dst = match output(&compare) {
+                    Some(dst) if same_location(dst, c) => dst,
+                    _ => continue,
+                };
+                let reads = insts.values().flat_map(inputs).filter(|&i| same_location(dst, i)).count();
+                if reads == 1 {
+                    let cond = match compare {
+                        Inst::LessThan(a, b, _) => Cond { lhs: frame.read(a), op: "<", rhs: frame.read(b) },
+                        Inst::Equal(a, b, _) => Cond { lhs: frame.read(a), op: "==", rhs: frame.read(b) },
+                        _ => continue,
+                    };
+                    skip.insert(prev);
+                    conds.insert(pc, cond);
+                }
+            }
+        }
+
+        let mut items = Vec::new();
+        for (&pc, inst) in insts.iter() {
+            let kind = if skip.contains(&pc) {
+                Kind::Skip
+            } else {
+                Self::kind(program, &frame, pc, inst, conds.remove(&pc))
+            };
+            items.push(Item { pc, end: pc + inst.len(), kind });
+        }
+        let index = items.iter().enumerate().map(|(i, item)| (item.pc, i)).collect();
+        Function { entry, frame, items, index }
+    }
+
+    fn kind(program: &Program, frame: &Frame, pc: usize, inst: &Inst, folded: Option<Cond>) -> Kind {
+        if let Some(call) = program.calls.get(&pc) {
+            let callee = program.frames.get(&call.target).copied().unwrap_or_default();
+            let site = &program.args[&pc];
+            let args: Vec<String> = (1..=callee.args).map(|j| match site.get(&j) {
+                Some(store) => frame.value(&program.insts[store]),
+                None => frame.slot(call.slot + j),
+            }).collect();
+            return Kind::Stmt(format!("{}({})", function_name(call.target), args.join(", ")));
+        }
+
+        let (c, t, jump_if) = match *inst {
+            Inst::JumpIfTrue(c, t) => (c, t, true),
+            Inst::JumpIfFalse(c, t) => (c, t, false),
+            Inst::AdjustBase(InParam::Immediate(n)) if n < 0 => return Kind::Stmt(format!("rb -= {}", n.unsigned_abs())),
+            Inst::AdjustBase(a) => return Kind::Stmt(format!("rb += {}", frame.read(a))),
+            Inst::Output(a) => return Kind::Stmt(format!("output({})", frame.read(a))),
+            Inst::Exit => return Kind::Stmt("halt".to_string()),
+            _ => match output(inst) {
+                Some(dst) => return Kind::Stmt(format!("{} = {}", frame.write(dst), frame.value(inst))),
+                None => return Kind::Skip,
+            },
+        };
+
+        // The condition for the jump being taken.
+        let cond = match folded {
+            Some(cond) if jump_if => cond,
+            Some(cond) => cond.negate(),
+            None => Cond { lhs: frame.read(c), op: if jump_if { "!=" } else { "==" }, rhs: "0".to_string() },
+        };
+        let target = jump_target(inst);
+        match c {
+            InParam::Immediate(v) if (v != 0) != jump_if => Kind::Skip,
+            InParam::Immediate(_) => match (target, t) {
+                (Some(target), _) => Kind::Goto(target),
+                (None, InParam::Relative(_)) => Kind::Stmt("return".to_string()),
+                (None, _) => Kind::Stmt(format!("goto *{}", frame.read(t))),
+            },
+            _ => match (target, t) {
+                (Some(target), _) => Kind::Branch { target, cond },
+                (None, InParam::Relative(_)) => Kind::Stmt(format!("if ({}) return", cond)),
+                (None, _) => Kind::Stmt(format!("if ({}) goto *{}", cond, frame.read(t))),
+            },
+        }
+    }
+
+    // Whether anything outside items[from..to] jumps to one of them other
+    // than the first.
+    fn entered(&self, from: usize, to: usize) -> bool {
+        let inside = |i: usize| i > from && i < to;
+        self.items.iter().enumerate().any(|(i, item)| {
+            !(from..to).contains(&i) && item.target().and_then(|t| self.index.get(&t)).is_some_and(|&t| inside(t))
+        })
+    }
+
+    fn jump(&self, pc: usize, target: usize, ctx: Context) -> Node {
+        if Some(target) == ctx.header {
+            Node::Continue(pc)
+        } else if Some(target) == ctx.exit {
+            Node::Break(pc)
+        } else {
+            Node::Goto(pc, target)
+        }
+    }
+
+    fn build(&self, lo: usize, hi: usize, ctx: Context) -> Vec<Node> {
+        let mut nodes = Vec::new();
+        let mut k = lo;
+        while k < hi {
+            let item = &self.items[k];
+
+            // The last jump back to here closes a loop.
+            let back = (k..hi).rev().find(|&m| self.items[m].target() == Some(item.pc));
+            if let Some(m) = back.filter(|&m| (k > lo || ctx.header != Some(item.pc)) && !self.entered(k, m + 1)) {
+                let inner = Context { header: Some(item.pc), exit: Some(self.items[m].end) };
+                let mut body = self.build(k, m, inner);
+                let cond = match &self.items[m].kind {
+                    Kind::Branch { cond, .. } if has_continue(&body) => {
+                        body.push(Node::If { pc: self.items[m].pc, cond: cond.negate(), then: vec!(Node::Break(self.items[m].pc)), els: vec!() });
+                        None
+                    }
+                    Kind::Branch { cond, .. } => Some(cond.clone()),
+                    _ => None,
+                };
+                nodes.push(Node::Loop { pc: item.pc, body, cond });
+                k = m + 1;
+                continue;
+            }
+
+            // A forward branch over code that nothing else jumps into is an
+            // if, and an else if the code ends with a jump over some more.
+            if let Kind::Branch { target, cond } = &item.kind {
+                let skipped = self.index.get(target).copied().filter(|&t| t > k && t <= hi && !self.entered(k + 1, t));
+                if let Some(t) = skipped {
+                    let over = match self.items[t - 1].kind {
+                        Kind::Goto(u) if t - 1 > k && u > *target => self.index.get(&u).copied().filter(|&u| u <= hi && !self.entered(t, u)),
+                        _ => None,
+                    };
+                    let node = match over {
+                        Some(u) => {
+                            let node = Node::If { pc: item.pc, cond: cond.negate(), then: self.build(k + 1, t - 1, ctx), els: self.build(t, u, ctx) };
+                            k = u;
+                            node
+                        }
+                        None => {
+                            let node = Node::If { pc: item.pc, cond: cond.negate(), then: self.build(k + 1, t, ctx), els: vec!() };
+                            k = t;
+                            node
+                        }
+                    };
+                    nodes.push(node);
+                    continue;
+                }
+            }
+
+            match &item.kind {
+                Kind::Skip => {}
+                Kind::Stmt(s) => nodes.push(Node::Stmt(item.pc, s.clone())),
+                Kind::Branch { target, cond } =>
+                    nodes.push(Node::If { pc: item.pc, cond: cond.clone(), then: vec!(self.jump(item.pc, *target, ctx)), els: vec!() }),
+                Kind::Goto(target) => nodes.push(self.jump(item.pc, *target, ctx)),
+            }
+            k += 1;
+        }
+        nodes
+    }
+
+    fn write(&self, out: &mut String) -> fmt::Result {
+        let nodes = self.build(0, self.items.len(), Context::default());
+        let mut labels = BTreeSet::new();
+        gotos(&nodes, &mut labels);
+
+        let params: Vec<String> = (1..=self.frame.args).map(|j| format!("arg{}", j)).collect();
+        writeln!(out, "fn {}({}) {{", function_name(self.entry), params.join(", "))?;
+        write_nodes(out, &nodes, 1, &labels)?;
+        writeln!(out, "}}")
+    }
+}
+
+fn function_name(entry: usize) -> String {
+    if entry == 0 { "main".to_string() } else { format!("f{}", entry) }
+}
+
+fn write_nodes(out: &mut String, nodes: &[Node], depth: usize, labels: &BTreeSet<usize>) -> fmt::Result {
+    let indent = "    ".repeat(depth);
+    for node in nodes {
+        if labels.contains(&node.pc()) {
+            writeln!(out, "{}L{}:", "    ".repeat(depth - 1), node.pc())?;
+        }
+        match node {
+            Node::Stmt(_, s) => writeln!(out, "{}{}", indent, s)?,
+            Node::Goto(_, target) => writeln!(out, "{}goto L{}", indent, target)?,
+            Node::Break(_) => writeln!(out, "{}break", indent)?,
+            Node::Continue(_) => writeln!(out, "{}continue", indent)?,
+            Node::If { cond, then, els, .. } => {
+                if let ([jump @ (Node::Goto(_, _) | Node::Break(_) | Node::Continue(_))], []) = (then.as_slice(), els.as_slice()) {
+                    let mut line = String::new();
+                    write_nodes(&mut line, std::slice::from_ref(jump), 0, &BTreeSet::new())?;
+                    write!(out, "{}if ({}) {}", indent, cond, line)?;
+                    continue;
+                }
+                writeln!(out, "{}if ({}) {{", indent, cond)?;
+                write_nodes(out, then, depth + 1, labels)?;
+                if !els.is_empty() {
+                    writeln!(out, "{}}} else {{", indent)?;
+                    write_nodes(out, els, depth + 1, labels)?;
+                }
+                writeln!(out, "{}}}", indent)?;
+            }
+            Node::Loop { body, cond, .. } => {
+                writeln!(out, "{}{} {{", indent, if cond.is_some() { "do" } else { "loop" })?;
+                write_nodes(out, body, depth + 1, labels)?;
+                match cond {
+                    Some(cond) => writeln!(out, "{}}} while ({})", indent, cond)?,
+                    None => writeln!(out, "{}}}", indent)?,
+                }
+            }
+        }
+    }
+    Ok(())
+}
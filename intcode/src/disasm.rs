@@ -0,0 +1,156 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::{decode, InParam, Inst, Word};
+
+const MAX_DATA_PER_LINE: usize = 8;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Listing {
+    pub address: usize,
+    pub words: Vec<Word>,
+    pub label: Option<String>,
+    // None for words that are never reached as code.
+    pub inst: Option<Inst>,
+    pub target_label: Option<String>,
+}
+
+impl Listing {
+    pub fn is_data(&self) -> bool {
+        self.inst.is_none()
+    }
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let words: Vec<String> = self.words.iter().map(|w| w.to_string()).collect();
+        let label = match &self.label {
+            Some(label) => format!("{}:", label),
+            None => String::new(),
+        };
+        write!(f, "{:>6}  {:<24} {:<8} ", self.address, words.join(","), label)?;
+
+        match (&self.inst, &self.target_label) {
+            (Some(Inst::JumpIfTrue(cond, _)), Some(target)) =>
+                write!(f, "JT {} imm:{}", cond, target),
+            (Some(Inst::JumpIfFalse(cond, _)), Some(target)) =>
+                write!(f, "JF {} imm:{}", cond, target),
+            (Some(inst), _) => write!(f, "{}", inst),
+            (None, _) => write!(f, ".data {}", words.join(" ")),
+        }
+    }
+}
+
+pub fn label_for(address: usize) -> String {
+    format!("L{}", address)
+}
+
+// Addresses of instructions that can be reached from pc 0 by following
+// fallthrough and immediate jump targets. Jumps through memory can't be
+// followed statically, so code that's only reached that way shows up as data.
+pub fn reachable(p: &[Word]) -> BTreeMap<usize, Inst> {
+    let mut result = BTreeMap::new();
+    let mut pending = vec!(0);
+
+    while let Some(pc) = pending.pop() {
+        if result.contains_key(&pc) {
+            continue;
+        }
+        let inst = match decode(p, pc) {
+            Ok(inst) => inst,
+            Err(_) => continue,
+        };
+        result.insert(pc, inst);
+        pending.extend(successors(&inst, pc));
+    }
+
+    result
+}
+
+pub fn successors(inst: &Inst, pc: usize) -> Vec<usize> {
+    let next = pc + inst.len();
+    match *inst {
+        Inst::Exit => vec!(),
+        Inst::JumpIfTrue(cond, target) => branch_successors(cond, target, next, true),
+        Inst::JumpIfFalse(cond, target) => branch_successors(cond, target, next, false),
+        _ => vec!(next),
+    }
+}
+
+fn branch_successors(cond: InParam, target: InParam, next: usize, jump_if: bool) -> Vec<usize> {
+    let target = match target {
+        InParam::Immediate(t) => usize::try_from(t).ok(),
+        _ => None,
+    };
+    match cond {
+        InParam::Immediate(c) if (c != 0) == jump_if => target.into_iter().collect(),
+        InParam::Immediate(_) => vec!(next),
+        _ => target.into_iter().chain(Some(next)).collect(),
+    }
+}
+
+pub fn jump_target(inst: &Inst) -> Option<usize> {
+    match inst {
+        Inst::JumpIfTrue(_, InParam::Immediate(t)) | Inst::JumpIfFalse(_, InParam::Immediate(t)) =>
+            usize::try_from(*t).ok(),
+        _ => None,
+    }
+}
+
+pub fn disassemble(p: &[Word]) -> Vec<Listing> {
+    let code = reachable(p);
+
+    let mut starts = BTreeMap::new();
+    let mut covered = 0;
+    for (&pc, inst) in code.iter() {
+        // Skip instructions that overlap one we've already placed.
+        if pc >= covered {
+            starts.insert(pc, *inst);
+            covered = pc + inst.len();
+        }
+    }
+
+    let targets: BTreeSet<usize> = starts.values()
+        .filter_map(jump_target)
+        .filter(|t| starts.contains_key(t))
+        .collect();
+    let label_at = |address: usize| {
+        if targets.contains(&address) {
+            Some(label_for(address))
+        } else {
+            None
+        }
+    };
+
+    let mut result = Vec::new();
+    let mut address = 0;
+    while address < p.len() {
+        if let Some(inst) = starts.get(&address) {
+            let end = address + inst.len();
+            result.push(Listing {
+                address,
+                words: p[address..end].to_vec(),
+                label: label_at(address),
+                inst: Some(*inst),
+                target_label: jump_target(inst).and_then(label_at),
+            });
+            address = end;
+        } else {
+            let mut end = address + 1;
+            while end < p.len() && end - address < MAX_DATA_PER_LINE && !starts.contains_key(&end) {
+                end += 1;
+            }
+            result.push(Listing {
+                address,
+                words: p[address..end].to_vec(),
+                label: None,
+                inst: None,
+                target_label: None,
+            });
+            address = end;
+        }
+    }
+
+    result
+}
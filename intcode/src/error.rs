@@ -0,0 +1,85 @@
+use std::fmt;
+
+use crate::Word;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Error<W = Word> {
+    BadPc { pc: usize },
+    BadOpcode { pc: usize, opcode: W },
+    BadMode { pc: usize, opcode: W, param: usize, mode: i8 },
+    Truncated { pc: usize, opcode: W },
+    BadLoadAddress { pc: usize, address: W },
+    BadStoreAddress { pc: usize, address: W },
+    BadJumpTarget { pc: usize, target: W },
+    OutOfFuel { pc: usize },
+    DeadlineExceeded { pc: usize },
+    MemoryLimit { pc: usize, address: usize },
+    // lhs and rhs are the operands of the add or multiply at pc, or the
+    // relative base and offset for an address or base adjustment.
+    Overflow { pc: usize, lhs: W, rhs: W },
+}
+
+impl<W> Error<W> {
+    pub fn pc(&self) -> usize {
+        match *self {
+            Error::BadPc { pc } => pc,
+            Error::BadOpcode { pc, .. } => pc,
+            Error::BadMode { pc, .. } => pc,
+            Error::Truncated { pc, .. } => pc,
+            Error::BadLoadAddress { pc, .. } => pc,
+            Error::BadStoreAddress { pc, .. } => pc,
+            Error::BadJumpTarget { pc, .. } => pc,
+            Error::OutOfFuel { pc } => pc,
+            Error::DeadlineExceeded { pc } => pc,
+            Error::MemoryLimit { pc, .. } => pc,
+            Error::Overflow { pc, .. } => pc,
+        }
+    }
+
+    pub(crate) fn with_pc(self, pc: usize) -> Self {
+        match self {
+            Error::BadPc { .. } => Error::BadPc { pc },
+            Error::BadOpcode { opcode, .. } => Error::BadOpcode { pc, opcode },
+            Error::BadMode { opcode, param, mode, .. } => Error::BadMode { pc, opcode, param, mode },
+            Error::Truncated { opcode, .. } => Error::Truncated { pc, opcode },
+            Error::BadLoadAddress { address, .. } => Error::BadLoadAddress { pc, address },
+            Error::BadStoreAddress { address, .. } => Error::BadStoreAddress { pc, address },
+            Error::BadJumpTarget { target, .. } => Error::BadJumpTarget { pc, target },
+            Error::OutOfFuel { .. } => Error::OutOfFuel { pc },
+            Error::DeadlineExceeded { .. } => Error::DeadlineExceeded { pc },
+            Error::MemoryLimit { address, .. } => Error::MemoryLimit { pc, address },
+            Error::Overflow { lhs, rhs, .. } => Error::Overflow { pc, lhs, rhs },
+        }
+    }
+}
+
+impl<W: fmt::Display> fmt::Display for Error<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::BadPc { pc } =>
+                write!(f, "pc {} is outside of memory", pc),
+            Error::BadOpcode { pc, opcode } =>
+                write!(f, "{}: unrecognized opcode {}", pc, opcode),
+            Error::BadMode { pc, opcode, param, mode } =>
+                write!(f, "{}: unrecognized mode {} for parameter {} of {}", pc, mode, param, opcode),
+            Error::Truncated { pc, opcode } =>
+                write!(f, "{}: instruction {} runs past the end of memory", pc, opcode),
+            Error::BadLoadAddress { pc, address } =>
+                write!(f, "{}: bad load address {}", pc, address),
+            Error::BadStoreAddress { pc, address } =>
+                write!(f, "{}: bad store address {}", pc, address),
+            Error::BadJumpTarget { pc, target } =>
+                write!(f, "{}: bad jump target {}", pc, target),
+            Error::OutOfFuel { pc } =>
+                write!(f, "{}: instruction budget exhausted", pc),
+            Error::DeadlineExceeded { pc } =>
+                write!(f, "{}: deadline exceeded", pc),
+            Error::MemoryLimit { pc, address } =>
+                write!(f, "{}: store to {} would exceed the memory limit", pc, address),
+            Error::Overflow { pc, lhs, rhs } =>
+                write!(f, "{}: arithmetic overflow with operands {} and {}", pc, lhs, rhs),
+        }
+    }
+}
+
+impl<W: fmt::Debug + fmt::Display> std::error::Error for Error<W> {}
@@ -0,0 +1,118 @@
+use std::fmt;
+
+use crate::{Computer, Error, WatchEvent, Word};
+
+// Why resume() stopped.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Event {
+    Halted,
+    NeedsInput,
+    Output(Word),
+    // A watchpoint with WatchAction::Pause fired.
+    Paused(Vec<WatchEvent>),
+}
+
+// Why a run ended before getting what it was waiting for. None of these
+// are faults, so they're kept out of Error: the program is fine, and can
+// carry on once whatever it's waiting for turns up.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Stopped {
+    Halted,
+    InputNeeded,
+    Paused,
+    // Every machine in a network was waiting for input.
+    Deadlock,
+    // Waiting for input that can never arrive.
+    Disconnected,
+}
+
+impl fmt::Display for Stopped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", match self {
+            Stopped::Halted => "program halted",
+            Stopped::InputNeeded => "program is waiting for input",
+            Stopped::Paused => "stopped at a watchpoint",
+            Stopped::Deadlock => "deadlocked waiting for input",
+            Stopped::Disconnected => "waiting for input that can never arrive",
+        })
+    }
+}
+
+impl Computer {
+    // Runs until something happens that the caller needs to deal with. Outputs
+    // are returned one at a time, including any that were already queued.
+    pub fn resume(&mut self) -> Result<Event, Error> {
+        loop {
+            if let Some(value) = self.output.pop_front() {
+                return Ok(Event::Output(value));
+            }
+            if let Some(event) = self.step_for_event()? {
+                return Ok(event);
+            }
+        }
+    }
+
+    // Returns exactly n outputs. If the program stops or fails first, the
+    // outputs it did produce are left in the output queue.
+    pub fn run_until_output(&mut self, n: usize) -> Result<Result<Vec<Word>, Stopped>, Error> {
+        let mut outputs = Vec::with_capacity(n);
+        while outputs.len() < n {
+            let result = match self.resume() {
+                Ok(Event::Output(value)) => {
+                    outputs.push(value);
+                    continue;
+                }
+                Ok(event) => Ok(Err(stopped_by(event))),
+                Err(e) => Err(e),
+            };
+            for value in outputs.into_iter().rev() {
+                self.output.push_front(value);
+            }
+            return result;
+        }
+        Ok(Ok(outputs))
+    }
+
+    // Runs until the program wants input that isn't queued, and returns what
+    // it output along the way.
+    pub fn run_until_input(&mut self) -> Result<Result<Vec<Word>, Stopped>, Error> {
+        self.run_until(Event::NeedsInput)
+    }
+
+    // Runs until the program halts, and returns what it output along the way.
+    pub fn run_until_halt(&mut self) -> Result<Result<Vec<Word>, Stopped>, Error> {
+        self.run_until(Event::Halted)
+    }
+
+    fn run_until(&mut self, stop: Event) -> Result<Result<Vec<Word>, Stopped>, Error> {
+        loop {
+            match self.step_for_event()? {
+                Some(event) if event == stop => return Ok(Ok(self.take_output())),
+                Some(event) => return Ok(Err(stopped_by(event))),
+                None => {}
+            }
+        }
+    }
+
+    // Steps once, and returns anything other than output that happened.
+    fn step_for_event(&mut self) -> Result<Option<Event>, Error> {
+        let result = self.step()?;
+        Ok(if result.done {
+            Some(Event::Halted)
+        } else if result.input_needed {
+            Some(Event::NeedsInput)
+        } else if result.paused {
+            Some(Event::Paused(result.watch_hits))
+        } else {
+            None
+        })
+    }
+}
+
+fn stopped_by(event: Event) -> Stopped {
+    match event {
+        Event::Halted => Stopped::Halted,
+        Event::NeedsInput => Stopped::InputNeeded,
+        _ => Stopped::Paused,
+    }
+}
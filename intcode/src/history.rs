@@ -0,0 +1,179 @@
+use std::collections::VecDeque;
+
+use crate::memory::Replaced;
+use crate::{Computer, Int};
+
+// Everything an instruction changed, so that it can be undone.
+#[derive(Clone, Debug, Default)]
+struct UndoEntry<W> {
+    pc: usize,
+    relative_base: W,
+    instructions_executed: u64,
+    fuel: Option<u64>,
+    store: Option<Replaced<W>>,
+    input: Option<W>,
+    output: bool,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct History<W> {
+    entries: VecDeque<UndoEntry<W>>,
+    limit: Option<usize>,
+    pending: UndoEntry<W>,
+
+    // The machine as it was when recording started, plus every input it has
+    // consumed since, is enough to replay it to any point.
+    origin: Box<Computer<W>>,
+    consumed: Vec<W>,
+    executed: usize,
+}
+
+impl<W: Int> History<W> {
+    pub(crate) fn begin(&mut self, c: &Computer<W>) {
+        self.pending = UndoEntry {
+            pc: c.pc,
+            relative_base: c.relative_base.clone(),
+            instructions_executed: c.instructions_executed,
+            fuel: c.fuel,
+            ..UndoEntry::default()
+        };
+    }
+
+    pub(crate) fn record_store(&mut self, replaced: Replaced<W>) {
+        self.pending.store = Some(replaced);
+    }
+
+    pub(crate) fn record_input(&mut self, value: W) {
+        self.pending.input = Some(value);
+    }
+
+    pub(crate) fn record_output(&mut self) {
+        self.pending.output = true;
+    }
+
+    pub(crate) fn commit(&mut self) {
+        if let Some(value) = &self.pending.input {
+            self.consumed.push(value.clone());
+        }
+        self.entries.push_back(std::mem::take(&mut self.pending));
+        if let Some(limit) = self.limit {
+            while self.entries.len() > limit {
+                self.entries.pop_front();
+            }
+        }
+        self.executed += 1;
+    }
+}
+
+impl<W: Int> Computer<W> {
+    // Starts recording an undo log. With a limit, only the last `limit`
+    // instructions can be rewound, but replay_to still works from the point
+    // recording started.
+    pub fn enable_history(&mut self, limit: Option<usize>) {
+        let mut origin = self.clone();
+        origin.history = None;
+        origin.set_tracer(None);
+        origin.watchpoints.clear();
+
+        self.history = Some(History {
+            entries: VecDeque::new(),
+            limit,
+            pending: UndoEntry::default(),
+            origin: Box::new(origin),
+            consumed: Vec::new(),
+            executed: 0,
+        });
+    }
+
+    pub fn disable_history(&mut self) {
+        self.history = None;
+    }
+
+    // The number of instructions executed since recording started.
+    pub fn history_position(&self) -> usize {
+        self.history.as_ref().map_or(0, |h| h.executed)
+    }
+
+    // The number of instructions that can currently be rewound.
+    pub fn history_len(&self) -> usize {
+        self.history.as_ref().map_or(0, |h| h.entries.len())
+    }
+
+    // Undoes up to n instructions and returns how many were undone. Outputs
+    // that were already taken from the output queue aren't put back.
+    pub fn rewind(&mut self, n: usize) -> usize {
+        let mut history = match self.history.take() {
+            Some(h) => h,
+            None => return 0,
+        };
+
+        let mut undone = 0;
+        while undone < n {
+            let entry = match history.entries.pop_back() {
+                Some(entry) => entry,
+                None => break,
+            };
+            if let Some(replaced) = entry.store {
+                self.undo_write(replaced);
+            }
+            if let Some(value) = entry.input {
+                self.input.push_front(value);
+                history.consumed.pop();
+            }
+            if entry.output {
+                self.output.pop_back();
+            }
+            self.pc = entry.pc;
+            self.relative_base = entry.relative_base;
+            self.instructions_executed = entry.instructions_executed;
+            self.fuel = entry.fuel;
+            history.executed -= 1;
+            undone += 1;
+        }
+
+        self.history = Some(history);
+        undone
+    }
+
+    // Rewinds to the most recent point where the machine was about to execute
+    // the instruction at pc. Nothing changes if there's no such point in the
+    // log.
+    pub fn rewind_to_pc(&mut self, pc: usize) -> bool {
+        let distance = match &self.history {
+            Some(h) => h.entries.iter().rev().position(|e| e.pc == pc),
+            None => None,
+        };
+        match distance {
+            Some(d) => {
+                self.rewind(d + 1);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Returns a copy of the machine as it was after the first k instructions
+    // since recording started, rebuilt from the starting state and the
+    // recorded inputs. Its input queue holds the inputs that this machine
+    // went on to consume after that point.
+    pub fn replay_to(&self, k: usize) -> Option<Computer<W>> {
+        let history = self.history.as_ref()?;
+        if k > history.executed {
+            return None;
+        }
+
+        // The deadline was for the original run, and may well have passed.
+        let mut c = (*history.origin).clone();
+        c.deadline = None;
+        c.input = history.consumed.iter().cloned().collect();
+        c.enable_history(history.limit);
+
+        while c.history_position() < k {
+            let result = c.step().ok()?;
+            if result.done || result.input_needed {
+                return None;
+            }
+        }
+        Some(c)
+    }
+}
@@ -0,0 +1,128 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::num::ParseIntError;
+
+// What a Computer's memory cells can be. i64 is the default and is enough for
+// nearly every program, i128 gives more room, and with the "bigint" feature
+// BigInt never overflows at all.
+pub trait Int: Clone + Default + fmt::Debug + fmt::Display + Eq + Ord + Send + Sync + 'static {
+    fn from_i64(n: i64) -> Self;
+    // None if the value doesn't fit.
+    fn to_i64(&self) -> Option<i64>;
+    fn to_usize(&self) -> Option<usize>;
+
+    // None on overflow.
+    fn checked_add(&self, rhs: &Self) -> Option<Self>;
+    fn checked_mul(&self, rhs: &Self) -> Option<Self>;
+    fn wrapping_add(&self, rhs: &Self) -> Self;
+    fn wrapping_mul(&self, rhs: &Self) -> Self;
+    fn saturating_add(&self, rhs: &Self) -> Self;
+    fn saturating_mul(&self, rhs: &Self) -> Self;
+
+    fn parse(s: &str) -> Result<Self, ParseIntError>;
+
+    fn is_zero(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+macro_rules! impl_int {
+    ($($t:ty),*) => {$(
+        impl Int for $t {
+            fn from_i64(n: i64) -> Self {
+                <$t>::from(n)
+            }
+
+            fn to_i64(&self) -> Option<i64> {
+                i64::try_from(*self).ok()
+            }
+
+            fn to_usize(&self) -> Option<usize> {
+                usize::try_from(*self).ok()
+            }
+
+            fn checked_add(&self, rhs: &Self) -> Option<Self> {
+                <$t>::checked_add(*self, *rhs)
+            }
+
+            fn checked_mul(&self, rhs: &Self) -> Option<Self> {
+                <$t>::checked_mul(*self, *rhs)
+            }
+
+            fn wrapping_add(&self, rhs: &Self) -> Self {
+                <$t>::wrapping_add(*self, *rhs)
+            }
+
+            fn wrapping_mul(&self, rhs: &Self) -> Self {
+                <$t>::wrapping_mul(*self, *rhs)
+            }
+
+            fn saturating_add(&self, rhs: &Self) -> Self {
+                <$t>::saturating_add(*self, *rhs)
+            }
+
+            fn saturating_mul(&self, rhs: &Self) -> Self {
+                <$t>::saturating_mul(*self, *rhs)
+            }
+
+            fn parse(s: &str) -> Result<Self, ParseIntError> {
+                s.parse()
+            }
+        }
+    )*};
+}
+
+impl_int!(i64, i128);
+
+#[cfg(feature = "bigint")]
+impl Int for num_bigint::BigInt {
+    fn from_i64(n: i64) -> Self {
+        Self::from(n)
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        i64::try_from(self).ok()
+    }
+
+    fn to_usize(&self) -> Option<usize> {
+        usize::try_from(self).ok()
+    }
+
+    fn checked_add(&self, rhs: &Self) -> Option<Self> {
+        Some(self + rhs)
+    }
+
+    fn checked_mul(&self, rhs: &Self) -> Option<Self> {
+        Some(self * rhs)
+    }
+
+    fn wrapping_add(&self, rhs: &Self) -> Self {
+        self + rhs
+    }
+
+    fn wrapping_mul(&self, rhs: &Self) -> Self {
+        self * rhs
+    }
+
+    fn saturating_add(&self, rhs: &Self) -> Self {
+        self + rhs
+    }
+
+    fn saturating_mul(&self, rhs: &Self) -> Self {
+        self * rhs
+    }
+
+    // Goes through i64 so that numbers are written the same way as for the
+    // other types, and so that errors are the same too. A number that's only
+    // too big for an i64 is made of nothing but digits, so BigInt can't
+    // reject it.
+    fn parse(s: &str) -> Result<Self, ParseIntError> {
+        use std::num::IntErrorKind;
+
+        match s.parse::<i64>() {
+            Ok(n) => Ok(Self::from(n)),
+            Err(e) if matches!(e.kind(), IntErrorKind::PosOverflow | IntErrorKind::NegOverflow) => s.parse().map_err(|_| e),
+            Err(e) => Err(e),
+        }
+    }
+}
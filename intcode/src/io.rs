@@ -0,0 +1,32 @@
+use crate::{Computer, Error, StepResult, Word};
+
+// Whatever a program is talking to: a robot, an arcade cabinet, a terminal.
+pub trait IoDevice {
+    // Called when the program wants input. Returning None stops run_with, so
+    // the caller can come back later with more.
+    fn read(&mut self) -> Option<Word>;
+
+    fn write(&mut self, value: Word);
+}
+
+impl Computer {
+    // Runs until the program halts, a watchpoint pauses it, or the device has
+    // no input to give. Outputs go to the device as soon as they're produced.
+    pub fn run_with(&mut self, device: &mut dyn IoDevice) -> Result<StepResult, Error> {
+        loop {
+            let result = self.step()?;
+            for value in self.output.drain(..) {
+                device.write(value);
+            }
+            if result.done || result.paused {
+                return Ok(result);
+            }
+            if result.input_needed {
+                match device.read() {
+                    Some(value) => self.send_input(value),
+                    None => return Ok(result),
+                }
+            }
+        }
+    }
+}
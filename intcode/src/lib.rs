@@ -1,26 +1,88 @@
 
 use std::collections::VecDeque;
 use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub mod aot;
+mod ascii;
+#[cfg(feature = "async")]
+pub mod async_driver;
+mod cache;
+pub mod cfg;
+pub mod debugger;
+pub mod decompile;
+pub mod disasm;
+mod error;
+mod events;
+mod history;
+mod int;
+mod io;
+mod memory;
+pub mod optimize;
+mod parse;
+pub mod router;
+mod snapshot;
+pub mod threaded;
+mod topology;
+mod trace;
+mod watch;
+
+pub use ascii::AsciiOutput;
+#[cfg(feature = "async")]
+pub use async_driver::AsyncComputer;
+pub use disasm::{disassemble, Listing};
+pub use error::Error;
+pub use events::{Event, Stopped};
+pub use int::Int;
+pub use io::IoDevice;
+pub use memory::DEFAULT_MEMORY_LIMIT;
+pub use parse::{ParseError, ParseErrorKind};
+pub use snapshot::SnapshotError;
+pub use topology::Topology;
+pub use trace::{JsonTracer, RingTracer, SharedTracer, TraceEvent, Tracer, WriteTracer};
+pub use watch::{Access, WatchAction, WatchCallback, WatchEvent, WatchKind, Watchpoint};
+#[cfg(feature = "bigint")]
+pub use num_bigint::BigInt;
+
+// The type of a memory cell, unless a Computer is given another Int to use.
+pub type Word = i64;
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
-enum InParam {
-    Position(i64),
-    Immediate(i64),
-    Relative(i64),
+pub enum InParam<W = Word> {
+    Position(W),
+    Immediate(W),
+    Relative(W),
 }
 
-impl InParam {
-    fn with_mode(mode: i8, value: i64) -> Result<Self, &'static str> {
+impl<W: Int> InParam<W> {
+    fn with_mode(mode: i8, value: W) -> Option<Self> {
         match mode {
-            0 => Ok(Self::Position(value)),
-            1 => Ok(Self::Immediate(value)),
-            2 => Ok(Self::Relative(value)),
-            _ => Err("Unrecognized mode"),
+            0 => Some(Self::Position(value)),
+            1 => Some(Self::Immediate(value)),
+            2 => Some(Self::Relative(value)),
+            _ => None,
+        }
+    }
+
+    pub fn mode(&self) -> Word {
+        match self {
+            InParam::Position(_) => 0,
+            InParam::Immediate(_) => 1,
+            InParam::Relative(_) => 2,
+        }
+    }
+
+    pub fn value(&self) -> W {
+        match self {
+            InParam::Position(i) => i.clone(),
+            InParam::Immediate(i) => i.clone(),
+            InParam::Relative(i) => i.clone(),
         }
     }
 }
 
-impl fmt::Display for InParam {
+impl<W: fmt::Display> fmt::Display for InParam<W> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             InParam::Position(i) => 
@@ -34,22 +96,36 @@ impl fmt::Display for InParam {
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
-enum OutParam {
-    Position(i64),
-    Relative(i64),
+pub enum OutParam<W = Word> {
+    Position(W),
+    Relative(W),
 }
 
-impl OutParam {
-    fn with_mode(mode: i8, value: i64) -> Result<Self, &'static str> {
+impl<W: Int> OutParam<W> {
+    fn with_mode(mode: i8, value: W) -> Option<Self> {
         match mode {
-            0 => Ok(Self::Position(value)),
-            2 => Ok(Self::Relative(value)),
-            _ => Err("Unrecognized mode"),
+            0 => Some(Self::Position(value)),
+            2 => Some(Self::Relative(value)),
+            _ => None,
+        }
+    }
+
+    pub fn mode(&self) -> Word {
+        match self {
+            OutParam::Position(_) => 0,
+            OutParam::Relative(_) => 2,
+        }
+    }
+
+    pub fn value(&self) -> W {
+        match self {
+            OutParam::Position(i) => i.clone(),
+            OutParam::Relative(i) => i.clone(),
         }
     }
 }
 
-impl fmt::Display for OutParam {
+impl<W: fmt::Display> fmt::Display for OutParam<W> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             OutParam::Position(i) => 
@@ -61,21 +137,22 @@ impl fmt::Display for OutParam {
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
-enum Inst {
-    Add(InParam, InParam, OutParam),
-    Mult(InParam, InParam, OutParam),
-    Input(OutParam),
-    Output(InParam),
-    JumpIfTrue(InParam, InParam),
-    JumpIfFalse(InParam, InParam),
-    LessThan(InParam, InParam, OutParam),
-    Equal(InParam, InParam, OutParam),
-    AdjustBase(InParam),
+pub enum Inst<W = Word> {
+    Add(InParam<W>, InParam<W>, OutParam<W>),
+    Mult(InParam<W>, InParam<W>, OutParam<W>),
+    Input(OutParam<W>),
+    Output(InParam<W>),
+    JumpIfTrue(InParam<W>, InParam<W>),
+    JumpIfFalse(InParam<W>, InParam<W>),
+    LessThan(InParam<W>, InParam<W>, OutParam<W>),
+    Equal(InParam<W>, InParam<W>, OutParam<W>),
+    AdjustBase(InParam<W>),
     Exit,
 }
 
-impl Inst {
-    fn len(&self) -> usize {
+impl<W> Inst<W> {
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
         match *self {
             Inst::Add(_,_,_) => 4,
             Inst::Mult(_,_,_) => 4,
@@ -89,14 +166,84 @@ impl Inst {
             Inst::AdjustBase(_) => 2
         }
     }
+
+    pub fn opcode(&self) -> Word {
+        match *self {
+            Inst::Add(_,_,_) => 1,
+            Inst::Mult(_,_,_) => 2,
+            Inst::Input(_) => 3,
+            Inst::Output(_) => 4,
+            Inst::JumpIfTrue(_,_) => 5,
+            Inst::JumpIfFalse(_,_) => 6,
+            Inst::LessThan(_,_,_) => 7,
+            Inst::Equal(_,_,_) => 8,
+            Inst::AdjustBase(_) => 9,
+            Inst::Exit => 99,
+        }
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        match *self {
+            Inst::Add(_,_,_) => "ADD",
+            Inst::Mult(_,_,_) => "MULT",
+            Inst::Input(_) => "IN",
+            Inst::Output(_) => "OUT",
+            Inst::JumpIfTrue(_,_) => "JT",
+            Inst::JumpIfFalse(_,_) => "JF",
+            Inst::LessThan(_,_,_) => "LT",
+            Inst::Equal(_,_,_) => "EQ",
+            Inst::AdjustBase(_) => "ARB",
+            Inst::Exit => "HALT",
+        }
+    }
+}
+
+impl Inst {
+    // The inverse of decode: the words that make up this instruction.
+    pub fn encode(&self) -> Vec<Word> {
+        let params: Vec<(Word, Word)> = match *self {
+            Inst::Add(a, b, c) | Inst::Mult(a, b, c) | Inst::LessThan(a, b, c) | Inst::Equal(a, b, c) =>
+                vec!((a.mode(), a.value()), (b.mode(), b.value()), (c.mode(), c.value())),
+            Inst::Input(a) => vec!((a.mode(), a.value())),
+            Inst::Output(a) | Inst::AdjustBase(a) => vec!((a.mode(), a.value())),
+            Inst::JumpIfTrue(a, b) | Inst::JumpIfFalse(a, b) =>
+                vec!((a.mode(), a.value()), (b.mode(), b.value())),
+            Inst::Exit => vec!(),
+        };
+
+        let mut word = self.opcode();
+        let mut scale = 100;
+        for (mode, _) in params.iter() {
+            word += mode * scale;
+            scale *= 10;
+        }
+
+        let mut result = vec!(word);
+        result.extend(params.iter().map(|(_, value)| *value));
+        result
+    }
+}
+
+impl<W: fmt::Display> fmt::Display for Inst<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.mnemonic())?;
+        match self {
+            Inst::Add(a, b, c) | Inst::Mult(a, b, c) | Inst::LessThan(a, b, c) | Inst::Equal(a, b, c) =>
+                write!(f, " {} {} {}", a, b, c),
+            Inst::Input(a) => write!(f, " {}", a),
+            Inst::Output(a) | Inst::AdjustBase(a) => write!(f, " {}", a),
+            Inst::JumpIfTrue(a, b) | Inst::JumpIfFalse(a, b) => write!(f, " {} {}", a, b),
+            Inst::Exit => Ok(()),
+        }
+    }
 }
 
 fn modes(opcode: i64) -> [i8; 4] {
-    let mut result = [0 as i8,0,0,0];
+    let mut result = [0i8,0,0,0];
     let mut i = 0;
     let mut m = opcode / 100;
 
-    while m > 0 {
+    while m > 0 && i < result.len() {
         result[i] = (m % 10) as i8;
         m /= 10;
         i += 1;
@@ -105,297 +252,556 @@ fn modes(opcode: i64) -> [i8; 4] {
     result
 }
 
-fn decode(p: &[i64], pc: usize) -> Result<Inst, &'static str> {
+// The first nonzero mode past an instruction's parameters, if there is one.
+fn unused_mode(opcode: i64, params: usize) -> Option<(usize, i8)> {
+    let mut m = opcode / 100;
+    for _ in 0..params {
+        m /= 10;
+    }
+    let mut param = params;
+    while m != 0 {
+        if m % 10 != 0 {
+            return Some((param, (m % 10) as i8));
+        }
+        m /= 10;
+        param += 1;
+    }
+    None
+}
+
+pub fn decode(p: &[Word], pc: usize) -> Result<Inst, Error> {
+    decode_words(p, pc)
+}
+
+// decode for any type of word.
+pub fn decode_words<W: Int>(p: &[W], pc: usize) -> Result<Inst<W>, Error<W>> {
     if pc >= p.len() {
-        return Err("Bad PC");
+        return Err(Error::BadPc { pc });
     }
 
-    let opcode = p[pc] % 100;
-    let modes = modes(p[pc]);
+    // A word too big for an i64 can't be a valid instruction, since any
+    // modes past the third would have to be zero.
+    let word = &p[pc];
+    let bits = match word.to_i64() {
+        Some(bits) => bits,
+        None => return Err(Error::BadOpcode { pc, opcode: word.clone() }),
+    };
+    let opcode = bits % 100;
+    let params = match opcode {
+        1 | 2 | 7 | 8 => 3,
+        5 | 6 => 2,
+        3 | 4 | 9 => 1,
+        99 => 0,
+        _ => return Err(Error::BadOpcode { pc, opcode: word.clone() }),
+    };
+    if let Some((param, mode)) = unused_mode(bits, params) {
+        return Err(Error::BadMode { pc, opcode: word.clone(), param, mode });
+    }
+    let modes = modes(bits);
+    let truncated = Error::Truncated { pc, opcode: word.clone() };
+    let bad_mode = |param: usize| Error::BadMode { pc, opcode: word.clone(), param, mode: modes[param] };
     match opcode {
         1 => { // add
-            let inst = p.get(pc..pc + 4).ok_or("Bad add instruction")?;
+            let inst = p.get(pc..pc + 4).ok_or(truncated)?;
             Ok(Inst::Add(
-                InParam::with_mode(modes[0], inst[1])?,
-                InParam::with_mode(modes[1], inst[2])?,
-                OutParam::with_mode(modes[2], inst[3])?,
+                InParam::with_mode(modes[0], inst[1].clone()).ok_or_else(|| bad_mode(0))?,
+                InParam::with_mode(modes[1], inst[2].clone()).ok_or_else(|| bad_mode(1))?,
+                OutParam::with_mode(modes[2], inst[3].clone()).ok_or_else(|| bad_mode(2))?,
             ))
         }
 
         2 => { // multiply
-            let inst = p.get(pc..pc + 4).ok_or("Bad mul instruction")?;
+            let inst = p.get(pc..pc + 4).ok_or(truncated)?;
             Ok(Inst::Mult(
-                InParam::with_mode(modes[0], inst[1])?,
-                InParam::with_mode(modes[1], inst[2])?,
-                OutParam::with_mode(modes[2], inst[3])?,
+                InParam::with_mode(modes[0], inst[1].clone()).ok_or_else(|| bad_mode(0))?,
+                InParam::with_mode(modes[1], inst[2].clone()).ok_or_else(|| bad_mode(1))?,
+                OutParam::with_mode(modes[2], inst[3].clone()).ok_or_else(|| bad_mode(2))?,
             ))
         }
 
         3 => { // input
-            let inst = p.get(pc..pc + 2).ok_or("Bad input instruction")?;
-            Ok(Inst::Input(OutParam::with_mode(modes[0], inst[1])?))
+            let inst = p.get(pc..pc + 2).ok_or(truncated)?;
+            Ok(Inst::Input(OutParam::with_mode(modes[0], inst[1].clone()).ok_or_else(|| bad_mode(0))?))
         }
 
         4 => { // output
-            let inst = p.get(pc..pc + 2).ok_or("Bad output instruction")?;
-            Ok(Inst::Output(InParam::with_mode(modes[0], inst[1])?))
+            let inst = p.get(pc..pc + 2).ok_or(truncated)?;
+            Ok(Inst::Output(InParam::with_mode(modes[0], inst[1].clone()).ok_or_else(|| bad_mode(0))?))
         }
 
         5 => { // jump if true
-            let inst = p.get(pc..pc + 3).ok_or("Bad jump-if-true instruction")?;
+            let inst = p.get(pc..pc + 3).ok_or(truncated)?;
             Ok(Inst::JumpIfTrue(
-                InParam::with_mode(modes[0], inst[1])?,
-                InParam::with_mode(modes[1], inst[2])?
+                InParam::with_mode(modes[0], inst[1].clone()).ok_or_else(|| bad_mode(0))?,
+                InParam::with_mode(modes[1], inst[2].clone()).ok_or_else(|| bad_mode(1))?
             ))
         }
 
         6 => { // jump if false
-            let inst = p.get(pc..pc + 3).ok_or("Bad jump-if-false instruction")?;
+            let inst = p.get(pc..pc + 3).ok_or(truncated)?;
             Ok(Inst::JumpIfFalse(
-                InParam::with_mode(modes[0], inst[1])?,
-                InParam::with_mode(modes[1], inst[2])?
+                InParam::with_mode(modes[0], inst[1].clone()).ok_or_else(|| bad_mode(0))?,
+                InParam::with_mode(modes[1], inst[2].clone()).ok_or_else(|| bad_mode(1))?
             ))
         }
 
         7 => { // less than
-            let inst = p.get(pc..pc + 4).ok_or("Bad less-than instruction")?;
+            let inst = p.get(pc..pc + 4).ok_or(truncated)?;
             Ok(Inst::LessThan(
-                InParam::with_mode(modes[0], inst[1])?,
-                InParam::with_mode(modes[1], inst[2])?,
-                OutParam::with_mode(modes[2], inst[3])?,
+                InParam::with_mode(modes[0], inst[1].clone()).ok_or_else(|| bad_mode(0))?,
+                InParam::with_mode(modes[1], inst[2].clone()).ok_or_else(|| bad_mode(1))?,
+                OutParam::with_mode(modes[2], inst[3].clone()).ok_or_else(|| bad_mode(2))?,
             ))
         }
 
         8 => { // equal
-            let inst = p.get(pc..pc + 4).ok_or("Bad equal instruction")?;
+            let inst = p.get(pc..pc + 4).ok_or(truncated)?;
             Ok(Inst::Equal(
-                InParam::with_mode(modes[0], inst[1])?,
-                InParam::with_mode(modes[1], inst[2])?,
-                OutParam::with_mode(modes[2], inst[3])?,
+                InParam::with_mode(modes[0], inst[1].clone()).ok_or_else(|| bad_mode(0))?,
+                InParam::with_mode(modes[1], inst[2].clone()).ok_or_else(|| bad_mode(1))?,
+                OutParam::with_mode(modes[2], inst[3].clone()).ok_or_else(|| bad_mode(2))?,
             ))
         }
 
         9 => { // adjust relative base
-            let inst = p.get(pc..pc + 2).ok_or("Bad adjust instruction")?;
+            let inst = p.get(pc..pc + 2).ok_or(truncated)?;
             Ok(Inst::AdjustBase(
-                InParam::with_mode(modes[0], inst[1])?
+                InParam::with_mode(modes[0], inst[1].clone()).ok_or_else(|| bad_mode(0))?
             ))
         }
 
         99 => Ok(Inst::Exit),
 
-        _ => Err("Bad instruction")
+        _ => Err(Error::BadOpcode { pc, opcode: word.clone() })
     }
 }
 
-pub fn read_from_string(s: &str) -> Vec<i64> {
-    s.trim().split(',').map(|x| x.parse::<i64>().unwrap()).collect()
+pub fn read_from_string(s: &str) -> Result<Vec<Word>, ParseError> {
+    parse::parse_program(s, true)
+}
+
+// Like read_from_string, but only allows numbers, commas and whitespace.
+pub fn read_from_string_strict(s: &str) -> Result<Vec<Word>, ParseError> {
+    parse::parse_program(s, false)
+}
+
+// Like read_from_string, for a Computer with another type of word. With
+// BigInt, numbers can be any length.
+pub fn read_words<W: Int>(s: &str) -> Result<Vec<W>, ParseError> {
+    parse::parse_program(s, true)
 }
 
-pub fn read_from_path(path: &str) -> std::io::Result<Vec<i64>> {
+pub fn read_from_path(path: &str) -> std::io::Result<Vec<Word>> {
     let contents = std::fs::read_to_string(path)?;
-    let numbers: Vec<i64> = read_from_string(&contents);
+    let numbers: Vec<Word> = read_from_string(&contents)?;
     Ok(numbers)
 }
 
-pub struct StepResult {
+const DEADLINE_CHECK_INTERVAL: u64 = 1024;
+
+// What ADD and MULT do when the result doesn't fit in a word. Address and
+// relative base arithmetic always fails with Error::Overflow. None of this
+// matters for BigInt, which never overflows.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum OverflowPolicy {
+    #[default]
+    Error,
+    Wrapping,
+    Saturating,
+}
+
+impl OverflowPolicy {
+    fn add<W: Int>(self, pc: usize, lhs: W, rhs: W) -> Result<W, Error<W>> {
+        match self {
+            OverflowPolicy::Error => Int::checked_add(&lhs, &rhs).ok_or(Error::Overflow { pc, lhs, rhs }),
+            OverflowPolicy::Wrapping => Ok(Int::wrapping_add(&lhs, &rhs)),
+            OverflowPolicy::Saturating => Ok(Int::saturating_add(&lhs, &rhs)),
+        }
+    }
+
+    fn mul<W: Int>(self, pc: usize, lhs: W, rhs: W) -> Result<W, Error<W>> {
+        match self {
+            OverflowPolicy::Error => Int::checked_mul(&lhs, &rhs).ok_or(Error::Overflow { pc, lhs, rhs }),
+            OverflowPolicy::Wrapping => Ok(Int::wrapping_mul(&lhs, &rhs)),
+            OverflowPolicy::Saturating => Ok(Int::saturating_mul(&lhs, &rhs)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct StepResult<W = Word> {
     pub done: bool,
     pub input_needed: bool,
     pub output_available: bool,
+    // Set when a watchpoint with WatchAction::Pause fired during the step.
+    pub paused: bool,
+    pub watch_hits: Vec<WatchEvent<W>>,
 }
 
-impl StepResult {
-    fn ok(c: &Computer) -> Self {
-        StepResult{ done: false, input_needed: false, output_available: !c.output.is_empty() }
+impl<W: Int> StepResult<W> {
+    fn new(c: &mut Computer<W>, done: bool, input_needed: bool) -> Self {
+        let watch_hits = std::mem::take(&mut c.watch_hits);
+        StepResult{
+            done,
+            input_needed,
+            output_available: !c.output.is_empty(),
+            paused: !watch_hits.is_empty(),
+            watch_hits,
+        }
+    }
+
+    fn ok(c: &mut Computer<W>) -> Self {
+        Self::new(c, false, false)
     }
 
-    fn done(c: &Computer) -> Self {
-        StepResult{ done: true, input_needed: false, output_available: !c.output.is_empty() }
+    fn done(c: &mut Computer<W>) -> Self {
+        Self::new(c, true, false)
     }
 
-    fn input_needed(c: &Computer) -> Self {
-        StepResult{ done: false, input_needed: true, output_available: !c.output.is_empty() }
+    fn input_needed(c: &mut Computer<W>) -> Self {
+        Self::new(c, false, true)
     }
 }
 
 #[derive(Clone, Debug)]
-pub struct Computer {
-    pub mem: Vec<i64>,
+pub struct Computer<W = Word> {
+    pub mem: Vec<W>,
     pub pc: usize,
-    pub relative_base: i64,
-    pub input: VecDeque<i64>,
-    pub output: VecDeque<i64>,
-
-    pub enable_tracing: bool,
+    pub relative_base: W,
+    pub input: VecDeque<W>,
+    pub output: VecDeque<W>,
+
+    instructions_executed: u64,
+    fuel: Option<u64>,
+    deadline: Option<Instant>,
+
+    overflow: OverflowPolicy,
+    memory_limit: Option<usize>,
+    sparse: Option<memory::SparseMemory<W>>,
+    decode_cache: Option<cache::DecodeCache<W>>,
+    // The program whose compiled code was last found to match mem, see
+    // aot::can_run. Any write to memory clears it.
+    compiled_code: Option<(usize, usize)>,
+
+    tracer: Option<trace::TracerHandle<W>>,
+    history: Option<history::History<W>>,
+    watchpoints: Vec<Option<Watchpoint<W>>>,
+    watch_hits: Vec<WatchEvent<W>>,
 }
 
 impl Computer {
-    pub fn new(mem: Vec<i64>) -> Self {
-        Computer{ mem, pc: 0, relative_base: 0, input: VecDeque::new(), output: VecDeque::new(), enable_tracing: false }
+    pub fn new(mem: Vec<Word>) -> Self {
+        Self::with_memory(mem)
     }
 
-    pub fn load_from_string(s: &str) -> Self {
-        let mem = s.trim().split(',').map(|x| x.parse::<i64>().unwrap()).collect();
-        Computer{ mem, pc: 0, relative_base: 0, input: VecDeque::new(), output: VecDeque::new(), enable_tracing: false }
+    pub fn load_from_string(s: &str) -> Result<Self, ParseError> {
+        Ok(Self::new(read_from_string(s)?))
     }
 
     pub fn load_from_path(path: &str) -> std::io::Result<Self> {
-        let contents = std::fs::read_to_string(path)?;
-        Ok(Self::load_from_string(&contents))
+        Ok(Self::new(read_from_path(path)?))
     }
-    
-    fn trace(&self, s: &str) {
-        if self.enable_tracing {
-            println!("{}", s);
+}
+
+impl<W: Int> Computer<W> {
+    // Like new, for any type of word: Computer::<i128>::with_memory(p).
+    pub fn with_memory(mem: Vec<W>) -> Self {
+        Computer{
+            mem,
+            pc: 0,
+            relative_base: W::default(),
+            input: VecDeque::new(),
+            output: VecDeque::new(),
+            instructions_executed: 0,
+            fuel: None,
+            deadline: None,
+            overflow: OverflowPolicy::Error,
+            memory_limit: Some(DEFAULT_MEMORY_LIMIT),
+            sparse: None,
+            decode_cache: None,
+            compiled_code: None,
+            tracer: None,
+            history: None,
+            watchpoints: Vec::new(),
+            watch_hits: Vec::new(),
         }
     }
 
-    pub fn step(&mut self) -> Result<StepResult, &'static str> {
-        let inst = decode(&self.mem[..], self.pc)?;
+    // Installs a tracer that's called after every instruction, and returns a
+    // handle to it so the caller can get at what it recorded.
+    pub fn trace_with<T: Tracer<W> + Send + 'static>(&mut self, tracer: T) -> Arc<Mutex<T>> {
+        let shared = Arc::new(Mutex::new(tracer));
+        self.set_tracer(Some(shared.clone()));
+        shared
+    }
+
+    pub fn set_tracer(&mut self, tracer: Option<SharedTracer<W>>) {
+        self.tracer = tracer.map(trace::TracerHandle);
+    }
+
+    pub fn enable_tracing(&mut self) {
+        self.trace_with(WriteTracer::new(std::io::stdout()));
+    }
+
+    // Limits the number of instructions that can run before step() fails with
+    // Error::OutOfFuel. None removes the limit.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    pub fn set_time_limit(&mut self, limit: Duration) {
+        self.deadline = Some(Instant::now() + limit);
+    }
+
+    pub fn instructions_executed(&self) -> u64 {
+        self.instructions_executed
+    }
+
+    pub fn set_overflow_policy(&mut self, policy: OverflowPolicy) {
+        self.overflow = policy;
+    }
+
+    pub fn overflow_policy(&self) -> OverflowPolicy {
+        self.overflow
+    }
+
+    fn check_budget(&self) -> Result<(), Error<W>> {
+        if self.fuel == Some(0) {
+            return Err(Error::OutOfFuel { pc: self.pc });
+        }
+        // Reading the clock on every instruction would be slow.
+        if let Some(deadline) = self.deadline {
+            if self.instructions_executed.is_multiple_of(DEADLINE_CHECK_INTERVAL) && Instant::now() >= deadline {
+                return Err(Error::DeadlineExceeded { pc: self.pc });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn step(&mut self) -> Result<StepResult<W>, Error<W>> {
+        self.watch_hits.clear();
+        if let Some(mut history) = self.history.take() {
+            history.begin(self);
+            self.history = Some(history);
+        }
+        let inst = self.fetch()?;
+        let halted = inst == Inst::Exit;
+        // Halting doesn't use up any of the budget.
+        if !halted {
+            self.check_budget()?;
+        }
         let mut next_pc = self.pc + inst.len();
+        let mut operands = [None, None];
+        let mut stored = None;
 
         match &inst {
             Inst::Add(src1, src2, dst) => {
-                let p1 = self.load(&src1)?;
-                let p2 = self.load(&src2)?;
-                self.trace(&format!("{}: {} = ADD {} ({}) {} ({})", self.pc, dst, src1, p1, src2, p2));
-                self.store(&dst, p1 + p2)?;
+                let p1 = self.load(src1)?;
+                let p2 = self.load(src2)?;
+                operands = [Some(p1.clone()), Some(p2.clone())];
+                stored = Some(self.store(dst, self.overflow.add(self.pc, p1, p2)?)?);
             },
             Inst::Mult(src1, src2, dst) => {
-                let p1 = self.load(&src1)?;
-                let p2 = self.load(&src2)?;
-                self.trace(&format!("{}: {} = MULT {} ({}) {} ({})", self.pc, dst, src1, p1, src2, p2));
-                self.store(&dst, p1 * p2)?;
+                let p1 = self.load(src1)?;
+                let p2 = self.load(src2)?;
+                operands = [Some(p1.clone()), Some(p2.clone())];
+                stored = Some(self.store(dst, self.overflow.mul(self.pc, p1, p2)?)?);
             },
             Inst::Input(dst) => {
                 if let Some(input_value) = self.input.pop_front() {
-                    self.trace(&format!("{}: {} = INPUT {}", self.pc, dst, input_value));
-                    self.store(&dst, input_value)?;
+                    // If the store fails, the value is still there for
+                    // whoever deals with the error.
+                    match self.store(dst, input_value.clone()) {
+                        Ok(store) => stored = Some(store),
+                        Err(e) => {
+                            self.input.push_front(input_value);
+                            return Err(e);
+                        }
+                    }
+                    if let Some(history) = &mut self.history {
+                        history.record_input(input_value);
+                    }
                 } else {
-                    return Ok(StepResult::input_needed(&self));
+                    return Ok(StepResult::input_needed(self));
                 }
             },
             Inst::Output(src) => {
-                let p1 = self.load(&src)?;
-                self.trace(&format!("{}: OUTPUT {} ({})", self.pc, src, p1));
+                let p1 = self.load(src)?;
+                operands = [Some(p1.clone()), None];
+                if let Some(history) = &mut self.history {
+                    history.record_output();
+                }
                 self.output.push_back(p1);
             },
             Inst::JumpIfTrue(cond, target) => {
-                let cond_value = self.load(&cond)?;
-                let target_value = self.load(&target)? as usize;
-                self.trace(&format!("{}: IF {} ({}) GOTO {} ({})", self.pc, cond, cond_value, target, target_value));
-                if cond_value != 0 {
-                    next_pc = target_value;
+                let cond_value = self.load(cond)?;
+                let (target_value, target_pc) = self.jump_target(target)?;
+                let taken = !cond_value.is_zero();
+                operands = [Some(cond_value), Some(target_value)];
+                if taken {
+                    next_pc = target_pc;
                 }
             },
             Inst::JumpIfFalse(cond, target) => {
-                let cond_value = self.load(&cond)?;
-                let target_value = self.load(&target)? as usize;
-                self.trace(&format!("{}: IF NOT {} ({}) GOTO {} ({})", self.pc, cond, cond_value, target, target_value));
-                if cond_value == 0 {
-                    next_pc = target_value;
+                let cond_value = self.load(cond)?;
+                let (target_value, target_pc) = self.jump_target(target)?;
+                let taken = cond_value.is_zero();
+                operands = [Some(cond_value), Some(target_value)];
+                if taken {
+                    next_pc = target_pc;
                 }
             },
             Inst::LessThan(src1, src2, dst) => {
-                let p1 = self.load(&src1)?;
-                let p2 = self.load(&src2)?;
-                self.trace(&format!("{}: {} = {} ({}) < {} ({})", self.pc, dst, src1, p1, src2, p2));
-                if p1 < p2 {
-                    self.store(&dst, 1)?;
-                } else {
-                    self.store(&dst, 0)?;
-                }
+                let p1 = self.load(src1)?;
+                let p2 = self.load(src2)?;
+                let result = W::from_i64((p1 < p2) as i64);
+                operands = [Some(p1), Some(p2)];
+                stored = Some(self.store(dst, result)?);
             }
             Inst::Equal(src1, src2, dst) => {
-                let p1 = self.load(&src1)?;
-                let p2 = self.load(&src2)?;
-                self.trace(&format!("{}: {} = {} ({}) == {} ({})", self.pc, dst, src1, p1, src2, p2));
-                if p1 == p2 {
-                    self.store(&dst, 1)?;
-                } else {
-                    self.store(&dst, 0)?;
-                }
+                let p1 = self.load(src1)?;
+                let p2 = self.load(src2)?;
+                let result = W::from_i64((p1 == p2) as i64);
+                operands = [Some(p1), Some(p2)];
+                stored = Some(self.store(dst, result)?);
             }
             Inst::AdjustBase(src) => {
-                let p1 = self.load(&src)?;
-                self.relative_base += p1;
-                self.trace(&format!("{}: ADJUST BASE BY {} ({}), NOW {}", self.pc, src, p1, self.relative_base));
-            }
-            Inst::Exit => {
-                self.trace(&format!("{}: EXIT", self.pc));
-                return Ok(StepResult::done(&self));
+                let p1 = self.load(src)?;
+                self.relative_base = self.relative_address(&p1)?;
+                operands = [Some(p1), None];
             }
+            Inst::Exit => {}
         };
+
+        if let Some(tracer) = &self.tracer {
+            tracer.trace(&TraceEvent {
+                pc: self.pc,
+                inst,
+                operands,
+                store: stored,
+                relative_base: self.relative_base.clone(),
+            });
+        }
+
+        if halted {
+            return Ok(StepResult::done(self));
+        }
         self.pc = next_pc;
+        self.instructions_executed += 1;
+        if let Some(fuel) = &mut self.fuel {
+            *fuel -= 1;
+        }
+        if let Some(history) = &mut self.history {
+            history.commit();
+        }
 
-        Ok(StepResult::ok(&self))
+        Ok(StepResult::ok(self))
     }
-    
-    pub fn run(&mut self) -> Result<(), &'static str> {
+
+    // Runs until the program halts, needs input that isn't queued, or a
+    // watchpoint pauses it, and returns the result of the last step.
+    pub fn run(&mut self) -> Result<StepResult<W>, Error<W>> {
         loop {
+            self.run_cached();
             let result = self.step()?;
-            if result.done {
-                return Ok(());
+            if result.done || result.input_needed || result.paused {
+                return Ok(result);
             }
         }
     }
 
-    fn load(&self, param: &InParam) -> Result<i64, &'static str> {
-        match param {
-            InParam::Immediate(i) => Ok(*i),
-            InParam::Position(i) => {
-                if *i < 0 {
-                    return Err("Bad load address");
-                }
-                if *i >= self.mem.len() as i64 {
-                    return Ok(0);
-                }
-                Ok(self.mem[*i as usize])
-            },
-            InParam::Relative(i) => {
-                let i = i + self.relative_base;
-                if i < 0 {
-                    return Err("Bad rel load address");
-                }
-                if i >= self.mem.len() as i64 {
-                    return Ok(0);
-                }
-                Ok(self.mem[i as usize])
-            }
+    // Returns an id that can be passed to unwatch.
+    pub fn watch(&mut self, address: usize, kind: WatchKind, action: WatchAction<W>) -> usize {
+        self.watchpoints.push(Some(Watchpoint { address, kind, action }));
+        self.watchpoints.len() - 1
+    }
+
+    pub fn unwatch(&mut self, id: usize) -> bool {
+        match self.watchpoints.get_mut(id) {
+            Some(w) => w.take().is_some(),
+            None => false,
         }
     }
-    
-    fn store(&mut self, param: &OutParam, value: i64) -> Result<(), &'static str> {
-        match param {
-            OutParam::Position(i) => {
-                if *i < 0 {
-                    return Err("Bad store address");
-                }
-                if *i >= self.mem.len() as i64 {
-                    self.mem.resize(*i as usize + 1, 0);
-                }
-                self.mem[*i as usize] = value;
-                Ok(())
-            },
-            OutParam::Relative(i) => {
-                let i = i + self.relative_base;
-                if i < 0 {
-                    return Err("Bad rel store address");
-                }
-                if i >= self.mem.len() as i64 {
-                    self.mem.resize(i as usize + 1, 0);
-                }
-                self.mem[i as usize] = value;
-                Ok(())
+
+    fn check_watchpoints(&mut self, address: usize, access: Access, old_value: &W, new_value: &W) {
+        for w in self.watchpoints.iter().flatten() {
+            if !w.matches(address, access) {
+                continue;
+            }
+            let event = WatchEvent {
+                pc: self.pc,
+                address,
+                access,
+                old_value: old_value.clone(),
+                new_value: new_value.clone(),
+            };
+            match &w.action {
+                WatchAction::Pause => self.watch_hits.push(event),
+                WatchAction::Callback(f) => f(&event),
             }
         }
     }
-    
-    pub fn send_input(&mut self, value: i64) {
+
+    // Returns the target as loaded, and as a pc.
+    fn jump_target(&mut self, param: &InParam<W>) -> Result<(W, usize), Error<W>> {
+        let target = self.load(param)?;
+        match target.to_usize() {
+            Some(pc) => Ok((target, pc)),
+            None => Err(Error::BadJumpTarget { pc: self.pc, target }),
+        }
+    }
+
+    fn relative_address(&self, offset: &W) -> Result<W, Error<W>> {
+        let base = &self.relative_base;
+        Int::checked_add(base, offset)
+            .ok_or_else(|| Error::Overflow { pc: self.pc, lhs: base.clone(), rhs: offset.clone() })
+    }
+
+    fn load(&mut self, param: &InParam<W>) -> Result<W, Error<W>> {
+        let address = match param {
+            InParam::Immediate(i) => return Ok(i.clone()),
+            InParam::Position(i) => i.clone(),
+            InParam::Relative(i) => self.relative_address(i)?,
+        };
+        let address = address.to_usize().ok_or(Error::BadLoadAddress { pc: self.pc, address })?;
+        let value = self.read_mem(address);
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoints(address, Access::Read, &value, &value);
+        }
+        Ok(value)
+    }
+
+    // Returns the address and value that were written.
+    fn store(&mut self, param: &OutParam<W>, value: W) -> Result<(usize, W), Error<W>> {
+        let address = match param {
+            OutParam::Position(i) => i.clone(),
+            OutParam::Relative(i) => self.relative_address(i)?,
+        };
+        let address = address.to_usize().ok_or(Error::BadStoreAddress { pc: self.pc, address })?;
+        let replaced = self.write_word(address, value.clone())?;
+        let old_value = replaced.old_value();
+        if let Some(history) = &mut self.history {
+            history.record_store(replaced);
+        }
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoints(address, Access::Write, &old_value, &value);
+        }
+        Ok((address, value))
+    }
+
+    pub fn send_input(&mut self, value: W) {
         self.input.push_back(value);
     }
 
-    pub fn take_output(&mut self) -> Vec<i64> {
+    pub fn take_output(&mut self) -> Vec<W> {
         self.output.drain(..).collect()
     }
 }
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use crate::{decode_words, Computer, Error, Inst, Int};
+
+// Memory can grow to this many words before stores fail with
+// Error::MemoryLimit. That's far more than any real program uses, but small
+// enough that a store through a corrupt address can't take the process down.
+pub const DEFAULT_MEMORY_LIMIT: usize = 1 << 26;
+
+// Cells at or above start that are past the end of mem live in a map instead,
+// so a program that writes to a few far-off addresses doesn't need a Vec that
+// reaches all the way out to them.
+#[derive(Clone, Debug)]
+pub(crate) struct SparseMemory<W> {
+    start: usize,
+    cells: HashMap<usize, W>,
+}
+
+// What a write replaced, so that it can be undone.
+#[derive(Clone, Debug)]
+pub(crate) enum Replaced<W> {
+    Dense { address: usize, old_value: W, old_len: usize },
+    Sparse { address: usize, old_value: Option<W> },
+}
+
+impl<W: Int> Replaced<W> {
+    pub(crate) fn old_value(&self) -> W {
+        match self {
+            Replaced::Dense { old_value, .. } => old_value.clone(),
+            Replaced::Sparse { old_value, .. } => old_value.clone().unwrap_or_default(),
+        }
+    }
+}
+
+impl<W: Int> Computer<W> {
+    // None removes the limit.
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.memory_limit = limit;
+    }
+
+    pub fn memory_limit(&self) -> Option<usize> {
+        self.memory_limit
+    }
+
+    // Stores to addresses at or above start that are past the end of mem go
+    // to a sparse map rather than growing mem.
+    pub fn enable_sparse_memory(&mut self, start: usize) {
+        match &mut self.sparse {
+            Some(sparse) => sparse.start = start,
+            None => self.sparse = Some(SparseMemory { start, cells: HashMap::new() }),
+        }
+    }
+
+    pub fn sparse_memory_start(&self) -> Option<usize> {
+        self.sparse.as_ref().map(|s| s.start)
+    }
+
+    // The cells held in sparse memory, in address order.
+    pub fn sparse_cells(&self) -> Vec<(usize, W)> {
+        let mut cells: Vec<(usize, W)> = match &self.sparse {
+            Some(sparse) => sparse.cells.iter().map(|(&a, v)| (a, v.clone())).collect(),
+            None => Vec::new(),
+        };
+        cells.sort_unstable();
+        cells
+    }
+
+    // The number of words of memory in use, which is what the limit applies to.
+    pub fn memory_used(&self) -> usize {
+        self.mem.len() + self.sparse.as_ref().map_or(0, |s| s.cells.len())
+    }
+
+    pub fn read_mem(&self, address: usize) -> W {
+        if let Some(value) = self.mem.get(address) {
+            return value.clone();
+        }
+        match &self.sparse {
+            Some(sparse) => sparse.cells.get(&address).cloned().unwrap_or_default(),
+            None => W::default(),
+        }
+    }
+
+    // Writes a word without going through an instruction, so no history,
+    // tracing or watchpoints. Returns the old value.
+    pub fn write_mem(&mut self, address: usize, value: W) -> Result<W, Error<W>> {
+        Ok(self.write_word(address, value)?.old_value())
+    }
+
+    pub(crate) fn write_word(&mut self, address: usize, value: W) -> Result<Replaced<W>, Error<W>> {
+        self.compiled_code = None;
+        if let Some(cache) = &mut self.decode_cache {
+            cache.invalidate(address);
+        }
+        let old_len = self.mem.len();
+        if address < old_len {
+            let old_value = std::mem::replace(&mut self.mem[address], value);
+            return Ok(Replaced::Dense { address, old_value, old_len });
+        }
+
+        let limit = self.memory_limit.unwrap_or(usize::MAX);
+        let sparse_len = self.sparse.as_ref().map_or(0, |s| s.cells.len());
+        match &mut self.sparse {
+            Some(sparse) if address >= sparse.start => {
+                if !sparse.cells.contains_key(&address) && old_len + sparse_len >= limit {
+                    return Err(Error::MemoryLimit { pc: self.pc, address });
+                }
+                let old_value = sparse.cells.insert(address, value);
+                Ok(Replaced::Sparse { address, old_value })
+            }
+            _ => {
+                if address.checked_add(1).and_then(|len| len.checked_add(sparse_len)).is_none_or(|n| n > limit) {
+                    return Err(Error::MemoryLimit { pc: self.pc, address });
+                }
+                self.mem.resize(address + 1, W::default());
+                self.mem[address] = value;
+                Ok(Replaced::Dense { address, old_value: W::default(), old_len })
+            }
+        }
+    }
+
+    pub(crate) fn undo_write(&mut self, replaced: Replaced<W>) {
+        self.compiled_code = None;
+        if let Some(cache) = &mut self.decode_cache {
+            let (Replaced::Dense { address, .. } | Replaced::Sparse { address, .. }) = &replaced;
+            cache.invalidate(*address);
+        }
+        match replaced {
+            Replaced::Dense { address, old_len, .. } if address >= old_len => {
+                self.mem.truncate(old_len);
+                if let Some(cache) = &mut self.decode_cache {
+                    cache.truncate(old_len);
+                }
+            }
+            Replaced::Dense { address, old_value, .. } => self.mem[address] = old_value,
+            Replaced::Sparse { address, old_value } => {
+                if let Some(sparse) = &mut self.sparse {
+                    match old_value {
+                        Some(v) => sparse.cells.insert(address, v),
+                        None => sparse.cells.remove(&address),
+                    };
+                }
+            }
+        }
+    }
+
+    // Decodes the instruction at pc. With sparse memory, an instruction can
+    // run off the end of mem into zeroes or sparse cells.
+    pub(crate) fn fetch(&mut self) -> Result<Inst<W>, Error<W>> {
+        // Only instructions that were decoded from mem are cached, so a hit
+        // doesn't need any of the checks below.
+        if let Some(inst) = self.decode_cache.as_ref().and_then(|cache| cache.get(self.pc)) {
+            return Ok(inst);
+        }
+
+        // Near the top of the address space there may be fewer than four
+        // words left, which decodes as a bad pc or a truncated instruction.
+        let end = self.pc.saturating_add(4);
+        if self.sparse.is_some() && end > self.mem.len() {
+            let words: [W; 4] = std::array::from_fn(|i| self.read_mem(self.pc.saturating_add(i)));
+            return decode_words(&words[..end - self.pc], 0).map_err(|e| e.with_pc(self.pc));
+        }
+        let inst = decode_words(&self.mem, self.pc)?;
+        if let Some(cache) = &mut self.decode_cache {
+            cache.insert(self.pc, inst.clone());
+        }
+        Ok(inst)
+    }
+}
@@ -0,0 +1,467 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+
+use crate::cfg;
+use crate::disasm::reachable;
+use crate::{Computer, Error, InParam, Inst, OutParam, Word};
+
+// How long verify lets each run go before giving up on it.
+pub const VERIFY_FUEL: u64 = 10_000_000;
+
+// Rewrites a program into one that does the same thing in fewer or cheaper
+// steps:
+//
+//   Reads of a cell that ADD, MULT, LT or EQ with two immediate operands
+//   stored to earlier in the same block use the value as an immediate.
+//   Jumps to a jump go straight to where that one goes.
+//   Jumps that can never be taken, or that go to the next instruction, are
+//   removed, and so are stores that are overwritten later in the same block
+//   before anything could read them.
+//
+// Instructions that the program reads or writes as data are left alone.
+// Relative mode accesses are assumed to stay on the stack, away from code;
+// verify is there to catch programs where that isn't true.
+//
+// If every address in the program is an immediate operand, removed
+// instructions are squeezed out and addresses adjusted to match. Otherwise
+// everything stays where it is, and a run of removed instructions becomes a
+// single jump over them.
+pub fn optimize(p: &[Word]) -> Vec<Word> {
+    let mut p = p.to_vec();
+    let code = reachable(&p);
+    let protected = protected(&code);
+
+    fold_constants(&mut p, &code, &protected);
+    let code = reachable(&p);
+    thread_jumps(&mut p, &code, &protected);
+
+    let code = reachable(&p);
+    let mut removed = BTreeSet::new();
+    for (&pc, inst) in code.iter() {
+        if !protected.contains(&pc) && is_nop(pc, inst) {
+            removed.insert(pc);
+        }
+    }
+    removed.extend(dead_stores(&p, &code, &protected));
+
+    if relocatable(&code) {
+        compact(&p, &code, &removed)
+    } else {
+        jump_over(&mut p, &code, &protected, &removed);
+        p
+    }
+}
+
+// What a run stopped with. Errors are compared by kind only, because the
+// optimized program can fail at a different pc.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    Halted,
+    InputNeeded,
+    Failed(Error),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Mismatch {
+    // Which of the inputs it happened with.
+    pub input: usize,
+    pub original: (Vec<Word>, Outcome),
+    pub optimized: (Vec<Word>, Outcome),
+}
+
+// Runs both programs on each of inputs and checks that they produce the same
+// output and stop the same way.
+pub fn verify(original: &[Word], optimized: &[Word], inputs: &[Vec<Word>]) -> Result<(), Box<Mismatch>> {
+    for (i, input) in inputs.iter().enumerate() {
+        let a = run(original, input);
+        let b = run(optimized, input);
+        let same_stop = match (&a.1, &b.1) {
+            (Outcome::Failed(x), Outcome::Failed(y)) => std::mem::discriminant(x) == std::mem::discriminant(y),
+            (x, y) => x == y,
+        };
+        if a.0 != b.0 || !same_stop {
+            return Err(Box::new(Mismatch { input: i, original: a, optimized: b }));
+        }
+    }
+    Ok(())
+}
+
+fn run(p: &[Word], input: &[Word]) -> (Vec<Word>, Outcome) {
+    let mut c = Computer::new(p.to_vec());
+    c.input.extend(input);
+    c.set_fuel(Some(VERIFY_FUEL));
+    let outcome = match c.run() {
+        Ok(result) if result.done => Outcome::Halted,
+        Ok(_) => Outcome::InputNeeded,
+        Err(e) => Outcome::Failed(e),
+    };
+    (c.take_output(), outcome)
+}
+
+fn reads(inst: &Inst) -> Vec<InParam> {
+    match *inst {
+        Inst::Add(a, b, _) | Inst::Mult(a, b, _) | Inst::LessThan(a, b, _) | Inst::Equal(a, b, _) => vec!(a, b),
+        Inst::JumpIfTrue(a, b) | Inst::JumpIfFalse(a, b) => vec!(a, b),
+        Inst::Output(a) | Inst::AdjustBase(a) => vec!(a),
+        Inst::Input(_) | Inst::Exit => vec!(),
+    }
+}
+
+fn write(inst: &Inst) -> Option<OutParam> {
+    match *inst {
+        Inst::Add(_, _, dst) | Inst::Mult(_, _, dst) | Inst::LessThan(_, _, dst) | Inst::Equal(_, _, dst) => Some(dst),
+        Inst::Input(dst) => Some(dst),
+        _ => None,
+    }
+}
+
+// Every address the program reads or writes through a position mode operand.
+fn data_addresses(code: &BTreeMap<usize, Inst>) -> BTreeSet<usize> {
+    let mut result = BTreeSet::new();
+    for inst in code.values() {
+        for param in reads(inst) {
+            if let InParam::Position(a) = param {
+                result.extend(usize::try_from(a).ok());
+            }
+        }
+        if let Some(OutParam::Position(a)) = write(inst) {
+            result.extend(usize::try_from(a).ok());
+        }
+    }
+    result
+}
+
+// Instructions with a word that's accessed as data.
+fn protected(code: &BTreeMap<usize, Inst>) -> BTreeSet<usize> {
+    let data = data_addresses(code);
+    code.iter()
+        .filter(|&(&pc, inst)| data.range(pc..pc + inst.len()).next().is_some())
+        .map(|(&pc, _)| pc)
+        .collect()
+}
+
+// Replaces position mode reads of cells that hold a known constant with the
+// constant. A cell is known from a store of two immediates, into data rather
+// than code, until something else might store to it or the block ends. If
+// there are jumps through memory, any address the program mentions could be
+// where one lands, so that forgets everything too. Once nothing reads a
+// constant's cell, dead_stores can often remove the store.
+fn fold_constants(p: &mut [Word], code: &BTreeMap<usize, Inst>, protected: &BTreeSet<usize>) {
+    let cfg = cfg::analyze(p);
+    let code_words: BTreeSet<usize> = code.iter().flat_map(|(&pc, inst)| pc..pc + inst.len()).collect();
+    let mut entries = BTreeSet::new();
+    if cfg.indirect_jumps().next().is_some() {
+        for inst in code.values() {
+            for param in reads(inst) {
+                if let InParam::Immediate(v) = param {
+                    entries.extend(usize::try_from(v).ok());
+                }
+            }
+        }
+    }
+
+    for block in cfg.blocks.values() {
+        let mut known: BTreeMap<Word, Word> = BTreeMap::new();
+        for &(pc, inst) in block.insts.iter() {
+            if entries.contains(&pc) {
+                known.clear();
+            }
+            let inst = if protected.contains(&pc) {
+                inst
+            } else {
+                let folded = substitute(inst, &known);
+                p[pc..pc + folded.len()].copy_from_slice(&folded.encode());
+                folded
+            };
+
+            match write(&inst) {
+                Some(OutParam::Position(address)) => {
+                    known.remove(&address);
+                    let in_code = usize::try_from(address).map_or(true, |a| code_words.contains(&a));
+                    if let (false, false, Some(value)) = (in_code, protected.contains(&pc), constant(&inst)) {
+                        known.insert(address, value);
+                    }
+                }
+                Some(OutParam::Relative(_)) => known.clear(),
+                None => {}
+            }
+        }
+    }
+}
+
+// The value an instruction always stores, if it can be worked out without
+// running it.
+fn constant(inst: &Inst) -> Option<Word> {
+    match *inst {
+        Inst::Add(InParam::Immediate(a), InParam::Immediate(b), _) => a.checked_add(b),
+        Inst::Mult(InParam::Immediate(a), InParam::Immediate(b), _) => a.checked_mul(b),
+        Inst::LessThan(InParam::Immediate(a), InParam::Immediate(b), _) => Some((a < b) as Word),
+        Inst::Equal(InParam::Immediate(a), InParam::Immediate(b), _) => Some((a == b) as Word),
+        _ => None,
+    }
+}
+
+fn substitute(inst: Inst, known: &BTreeMap<Word, Word>) -> Inst {
+    let fold = |param: InParam| match param {
+        InParam::Position(a) => known.get(&a).map_or(param, |&v| InParam::Immediate(v)),
+        param => param,
+    };
+    match inst {
+        Inst::Add(a, b, c) => Inst::Add(fold(a), fold(b), c),
+        Inst::Mult(a, b, c) => Inst::Mult(fold(a), fold(b), c),
+        Inst::LessThan(a, b, c) => Inst::LessThan(fold(a), fold(b), c),
+        Inst::Equal(a, b, c) => Inst::Equal(fold(a), fold(b), c),
+        Inst::Output(a) => Inst::Output(fold(a)),
+        Inst::JumpIfTrue(a, b) => Inst::JumpIfTrue(fold(a), fold(b)),
+        Inst::JumpIfFalse(a, b) => Inst::JumpIfFalse(fold(a), fold(b)),
+        Inst::AdjustBase(a) => Inst::AdjustBase(fold(a)),
+        inst => inst,
+    }
+}
+
+// Where control ends up if it gets to pc, skipping over jumps that always go
+// somewhere else and ones that never do anything.
+fn final_target(code: &BTreeMap<usize, Inst>, protected: &BTreeSet<usize>, mut pc: usize) -> usize {
+    let mut seen = BTreeSet::new();
+    while seen.insert(pc) && !protected.contains(&pc) {
+        let inst = match code.get(&pc) {
+            Some(inst) => inst,
+            None => break,
+        };
+        pc = match unconditional_target(inst) {
+            Some(target) => target,
+            None if is_nop(pc, inst) => pc + inst.len(),
+            None => break,
+        };
+    }
+    pc
+}
+
+fn unconditional_target(inst: &Inst) -> Option<usize> {
+    match *inst {
+        Inst::JumpIfTrue(InParam::Immediate(c), InParam::Immediate(t)) if c != 0 => usize::try_from(t).ok(),
+        Inst::JumpIfFalse(InParam::Immediate(0), InParam::Immediate(t)) => usize::try_from(t).ok(),
+        _ => None,
+    }
+}
+
+fn thread_jumps(p: &mut [Word], code: &BTreeMap<usize, Inst>, protected: &BTreeSet<usize>) {
+    for (&pc, inst) in code.iter() {
+        if protected.contains(&pc) {
+            continue;
+        }
+        let target = match *inst {
+            Inst::JumpIfTrue(_, InParam::Immediate(t)) | Inst::JumpIfFalse(_, InParam::Immediate(t)) => t,
+            _ => continue,
+        };
+        if let Ok(t) = usize::try_from(target) {
+            p[pc + 2] = final_target(code, protected, t) as Word;
+        }
+    }
+}
+
+// A load that can't fail.
+fn safe(param: InParam) -> bool {
+    match param {
+        InParam::Immediate(_) => true,
+        InParam::Position(a) => a >= 0,
+        InParam::Relative(_) => false,
+    }
+}
+
+// A jump that never goes anywhere but the next instruction, and can't fail.
+// The target is checked even when the jump isn't taken.
+fn is_nop(pc: usize, inst: &Inst) -> bool {
+    let (cond, target, jump_if) = match *inst {
+        Inst::JumpIfTrue(cond, InParam::Immediate(target)) => (cond, target, true),
+        Inst::JumpIfFalse(cond, InParam::Immediate(target)) => (cond, target, false),
+        _ => return false,
+    };
+    match cond {
+        _ if target < 0 || !safe(cond) => false,
+        InParam::Immediate(c) if (c != 0) != jump_if => true,
+        _ => usize::try_from(target) == Ok(pc + inst.len()),
+    }
+}
+
+// Stores to a fixed address that's stored to again later in the same block,
+// with nothing in between that reads it. Anything using relative mode might
+// read it, so that ends the search. A store into code is read when that
+// instruction runs, so those are never dead. Only stores that can't fail
+// count, so that removing one can't hide an error.
+fn dead_stores(p: &[Word], code: &BTreeMap<usize, Inst>, protected: &BTreeSet<usize>) -> Vec<usize> {
+    let code_words: BTreeSet<usize> = code.iter().flat_map(|(&pc, inst)| pc..pc + inst.len()).collect();
+    let mut result = Vec::new();
+    for block in cfg::analyze(p).blocks.values() {
+        for (i, &(pc, inst)) in block.insts.iter().enumerate() {
+            let address = match (inst, write(&inst)) {
+                (Inst::Add(a, b, _), Some(OutParam::Position(address))) | (Inst::Mult(a, b, _), Some(OutParam::Position(address)))
+                    if matches!((a, b), (InParam::Immediate(_), InParam::Immediate(_))) => address,
+                (Inst::LessThan(a, b, _), Some(OutParam::Position(address))) | (Inst::Equal(a, b, _), Some(OutParam::Position(address)))
+                    if safe(a) && safe(b) => address,
+                _ => continue,
+            };
+            let in_code = usize::try_from(address).map_or(true, |a| code_words.contains(&a));
+            if in_code || protected.contains(&pc) {
+                continue;
+            }
+
+            for &(_, later) in block.insts[i + 1..].iter() {
+                let params = reads(&later);
+                if params.contains(&InParam::Position(address))
+                    || params.iter().any(|param| matches!(param, InParam::Relative(_)))
+                    || matches!(write(&later), Some(OutParam::Relative(_))) {
+                    break;
+                }
+                if write(&later) == Some(OutParam::Position(address)) {
+                    result.push(pc);
+                    break;
+                }
+            }
+        }
+    }
+    result
+}
+
+// Whether every address the program uses is an immediate operand, so that
+// code can be moved as long as the operands are fixed up to match: no
+// relative mode, no jumps through memory, no instructions that overlap, and
+// nothing that accesses code as data.
+fn relocatable(code: &BTreeMap<usize, Inst>) -> bool {
+    let data = data_addresses(code);
+    let mut covered = 0;
+    for (&pc, inst) in code.iter() {
+        if pc < covered || data.range(pc..pc + inst.len()).next().is_some() {
+            return false;
+        }
+        covered = pc + inst.len();
+
+        let relative = reads(inst).iter().any(|p| matches!(p, InParam::Relative(_)))
+            || matches!(write(inst), Some(OutParam::Relative(_)));
+        let indirect = matches!(inst,
+            Inst::JumpIfTrue(_, t) | Inst::JumpIfFalse(_, t) if !matches!(t, InParam::Immediate(_)));
+        if relative || indirect || matches!(inst, Inst::AdjustBase(_)) {
+            return false;
+        }
+    }
+    true
+}
+
+fn compact(p: &[Word], code: &BTreeMap<usize, Inst>, removed: &BTreeSet<usize>) -> Vec<Word> {
+    // Where each old address ends up. The words of a removed instruction go
+    // to whatever comes after it.
+    let mut moved = vec!(0; p.len() + 1);
+    let mut next = 0;
+    let mut pc = 0;
+    while pc < p.len() {
+        match code.get(&pc) {
+            Some(inst) => {
+                let len = inst.len();
+                if removed.contains(&pc) {
+                    moved[pc..pc + len].fill(next);
+                } else {
+                    for (i, word) in moved[pc..pc + len].iter_mut().enumerate() {
+                        *word = next + i;
+                    }
+                    next += len;
+                }
+                pc += len;
+            }
+            None => {
+                moved[pc] = next;
+                next += 1;
+                pc += 1;
+            }
+        }
+    }
+    moved[p.len()] = next;
+    let shrunk = (p.len() - next) as Word;
+
+    let relocate = |address: Word| -> Word {
+        match usize::try_from(address) {
+            Ok(a) if a <= p.len() => moved[a] as Word,
+            Ok(_) => address - shrunk,
+            Err(_) => address,
+        }
+    };
+    let relocate_in = |param: InParam| match param {
+        InParam::Position(a) => InParam::Position(relocate(a)),
+        param => param,
+    };
+    let relocate_out = |param: OutParam| match param {
+        OutParam::Position(a) => OutParam::Position(relocate(a)),
+        param => param,
+    };
+    let relocate_target = |param: InParam| match param {
+        InParam::Immediate(t) => InParam::Immediate(relocate(t)),
+        param => relocate_in(param),
+    };
+
+    let mut result = Vec::with_capacity(next);
+    let mut pc = 0;
+    while pc < p.len() {
+        let inst = match code.get(&pc) {
+            Some(inst) => inst,
+            None => {
+                result.push(p[pc]);
+                pc += 1;
+                continue;
+            }
+        };
+        pc += inst.len();
+        if removed.contains(&(pc - inst.len())) {
+            continue;
+        }
+        let relocated = match *inst {
+            Inst::Add(a, b, c) => Inst::Add(relocate_in(a), relocate_in(b), relocate_out(c)),
+            Inst::Mult(a, b, c) => Inst::Mult(relocate_in(a), relocate_in(b), relocate_out(c)),
+            Inst::LessThan(a, b, c) => Inst::LessThan(relocate_in(a), relocate_in(b), relocate_out(c)),
+            Inst::Equal(a, b, c) => Inst::Equal(relocate_in(a), relocate_in(b), relocate_out(c)),
+            Inst::Input(a) => Inst::Input(relocate_out(a)),
+            Inst::Output(a) => Inst::Output(relocate_in(a)),
+            Inst::JumpIfTrue(a, b) => Inst::JumpIfTrue(relocate_in(a), relocate_target(b)),
+            Inst::JumpIfFalse(a, b) => Inst::JumpIfFalse(relocate_in(a), relocate_target(b)),
+            inst => inst,
+        };
+        result.extend(relocated.encode());
+    }
+    result
+}
+
+// Replaces each run of removed instructions with a jump past the end of it,
+// and points jumps into the run there too. The rest of the run's words stay
+// as they were. Jumps that are read as data keep their targets.
+fn jump_over(p: &mut [Word], code: &BTreeMap<usize, Inst>, protected: &BTreeSet<usize>, removed: &BTreeSet<usize>) {
+    let mut runs: Vec<(usize, usize, usize)> = Vec::new();
+    for &pc in removed.iter() {
+        let end = pc + code[&pc].len();
+        match runs.last_mut() {
+            Some((_, last_end, count)) if *last_end == pc => {
+                *last_end = end;
+                *count += 1;
+            }
+            _ => runs.push((pc, end, 1)),
+        }
+    }
+
+    let run_end = |address: usize| runs.iter().find(|&&(start, end, _)| start <= address && address < end).map(|r| r.1);
+    for (&pc, inst) in code.iter() {
+        if removed.contains(&pc) || protected.contains(&pc) {
+            continue;
+        }
+        if let Inst::JumpIfTrue(_, InParam::Immediate(t)) | Inst::JumpIfFalse(_, InParam::Immediate(t)) = *inst {
+            if let Some(end) = usize::try_from(t).ok().and_then(run_end) {
+                p[pc + 2] = end as Word;
+            }
+        }
+    }
+
+    for &(start, end, count) in runs.iter() {
+        // A single jump is already no slower than its replacement.
+        if count == 1 && matches!(code[&start], Inst::JumpIfTrue(_, _) | Inst::JumpIfFalse(_, _)) {
+            continue;
+        }
+        let jump = Inst::JumpIfTrue(InParam::Immediate(1), InParam::Immediate(end as Word));
+        p[start..start + jump.len()].copy_from_slice(&jump.encode());
+    }
+}
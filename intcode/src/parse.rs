@@ -0,0 +1,101 @@
+use std::fmt;
+use std::num::ParseIntError;
+
+use crate::Int;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ParseErrorKind {
+    EmptyValue,
+    MissingComma,
+    BadNumber(String, ParseIntError),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParseError {
+    pub index: usize,
+    pub offset: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "value {} at byte {}: ", self.index, self.offset)?;
+        match &self.kind {
+            ParseErrorKind::EmptyValue => write!(f, "missing value"),
+            ParseErrorKind::MissingComma => write!(f, "expected a comma"),
+            ParseErrorKind::BadNumber(token, e) => write!(f, "bad number {:?} ({})", token, e),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<ParseError> for std::io::Error {
+    fn from(e: ParseError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+    }
+}
+
+// Programs are comma-separated integers. Whitespace (including newlines) is
+// allowed between values, and a single trailing comma is ignored. With
+// comments, '#' starts a comment that runs to the end of the line; without
+// them it's just a bad character.
+pub fn parse_program<W: Int>(s: &str, comments: bool) -> Result<Vec<W>, ParseError> {
+    let mut result = Vec::new();
+    let mut token: Option<(usize, usize)> = None;
+    let mut token_ended = false;
+    let mut in_comment = false;
+
+    for (offset, ch) in s.char_indices() {
+        if in_comment {
+            in_comment = ch != '\n';
+            continue;
+        }
+
+        match ch {
+            '#' if comments => {
+                in_comment = true;
+                if token.is_some() {
+                    token_ended = true;
+                }
+            }
+            ',' => {
+                let index = result.len();
+                match token.take() {
+                    Some((start, end)) => result.push(parse_value(&s[start..end], index, start)?),
+                    None => return Err(ParseError { index, offset, kind: ParseErrorKind::EmptyValue }),
+                }
+                token_ended = false;
+            }
+            _ if ch.is_whitespace() => {
+                if token.is_some() {
+                    token_ended = true;
+                }
+            }
+            _ => {
+                match token {
+                    Some(_) if token_ended => {
+                        return Err(ParseError { index: result.len(), offset, kind: ParseErrorKind::MissingComma });
+                    }
+                    Some((start, _)) => token = Some((start, offset + ch.len_utf8())),
+                    None => token = Some((offset, offset + ch.len_utf8())),
+                }
+            }
+        }
+    }
+
+    if let Some((start, end)) = token {
+        let index = result.len();
+        result.push(parse_value(&s[start..end], index, start)?);
+    }
+
+    Ok(result)
+}
+
+fn parse_value<W: Int>(token: &str, index: usize, offset: usize) -> Result<W, ParseError> {
+    W::parse(token).map_err(|e| ParseError {
+        index,
+        offset,
+        kind: ParseErrorKind::BadNumber(token.to_string(), e),
+    })
+}
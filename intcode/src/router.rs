@@ -0,0 +1,176 @@
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::{Computer, Error, Word};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Packet {
+    pub dest: Word,
+    pub x: Word,
+    pub y: Word,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RouterEvent {
+    // A machine sent a packet, to another machine or to the NAT.
+    Sent(Packet),
+    // A packet was sent to an address that nothing is listening on, or to a
+    // machine that has halted, possibly before it got to read it.
+    Dropped(Packet),
+    // The network went idle, so the NAT sent the last packet it received.
+    NatSent(Packet),
+}
+
+// The target passed to with_nat isn't one of the machines.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BadNatTarget {
+    pub target: usize,
+    pub machines: usize,
+}
+
+impl fmt::Display for BadNatTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "NAT target {} is out of range for {} machines", self.target, self.machines)
+    }
+}
+
+impl std::error::Error for BadNatTarget {}
+
+// Runs machines that each read their address as their first input, then talk
+// to each other in (destination, x, y) packets. A machine that asks for input
+// when nothing has been sent to it gets -1. Machines that halt are left out
+// from then on.
+//
+// Packets sent to the NAT's address aren't delivered. The NAT keeps the last
+// one, and when the network has been idle for a while, sends it to nat_target.
+#[derive(Clone, Debug)]
+pub struct Router {
+    machines: Vec<Computer>,
+    halted: Vec<bool>,
+    queues: Vec<VecDeque<(Word, Word)>>,
+    nat_address: Option<Word>,
+    nat_target: usize,
+    nat_packet: Option<(Word, Word)>,
+    idle_threshold: usize,
+    idle_rounds: usize,
+}
+
+impl Router {
+    pub fn new(p: &[Word], n: usize) -> Self {
+        let machines = (0..n).map(|address| {
+            let mut c = Computer::new(p.to_vec());
+            c.send_input(address as Word);
+            c
+        }).collect();
+        Router {
+            machines,
+            halted: vec!(false; n),
+            queues: vec!(VecDeque::new(); n),
+            nat_address: None,
+            nat_target: 0,
+            nat_packet: None,
+            idle_threshold: 2,
+            idle_rounds: 0,
+        }
+    }
+
+    pub fn with_nat(mut self, address: Word, target: usize) -> Result<Self, BadNatTarget> {
+        if target >= self.machines.len() {
+            return Err(BadNatTarget { target, machines: self.machines.len() });
+        }
+        self.nat_address = Some(address);
+        self.nat_target = target;
+        Ok(self)
+    }
+
+    // The number of rounds in a row with nothing queued and nothing sent
+    // before the NAT steps in.
+    pub fn set_idle_threshold(&mut self, rounds: usize) {
+        self.idle_threshold = rounds;
+    }
+
+    pub fn machines_mut(&mut self) -> &mut [Computer] {
+        &mut self.machines
+    }
+
+    pub fn send(&mut self, packet: Packet) -> RouterEvent {
+        if Some(packet.dest) == self.nat_address {
+            self.nat_packet = Some((packet.x, packet.y));
+            return RouterEvent::Sent(packet);
+        }
+        match usize::try_from(packet.dest) {
+            Ok(dest) if dest < self.queues.len() && !self.halted[dest] => {
+                self.queues[dest].push_back((packet.x, packet.y));
+                RouterEvent::Sent(packet)
+            }
+            _ => RouterEvent::Dropped(packet),
+        }
+    }
+
+    // Gives every machine that's still running one turn: the next packet
+    // queued for it, or -1, and then runs it until it wants more input.
+    pub fn round(&mut self) -> Result<Vec<RouterEvent>, Error> {
+        let mut events = Vec::new();
+        for i in 0..self.machines.len() {
+            if self.halted[i] {
+                continue;
+            }
+            let c = &mut self.machines[i];
+            if c.input.is_empty() {
+                match self.queues[i].pop_front() {
+                    Some((x, y)) => {
+                        c.send_input(x);
+                        c.send_input(y);
+                    }
+                    None => c.send_input(-1),
+                }
+            }
+            let done = c.run()?.done;
+
+            // A packet that's only partly written stays until the rest of it is.
+            let complete = c.output.len() / 3 * 3;
+            let words: Vec<Word> = c.output.drain(..complete).collect();
+            if done {
+                self.halted[i] = true;
+                for (x, y) in self.queues[i].drain(..) {
+                    events.push(RouterEvent::Dropped(Packet { dest: i as Word, x, y }));
+                }
+            }
+            for packet in words.chunks(3) {
+                events.push(self.send(Packet { dest: packet[0], x: packet[1], y: packet[2] }));
+            }
+        }
+
+        if events.is_empty() && self.queues.iter().all(|q| q.is_empty()) {
+            self.idle_rounds += 1;
+        } else {
+            self.idle_rounds = 0;
+        }
+        if self.idle_rounds >= self.idle_threshold {
+            if let Some((x, y)) = self.nat_packet {
+                let packet = Packet { dest: self.nat_target as Word, x, y };
+                self.idle_rounds = 0;
+                if self.halted[self.nat_target] {
+                    self.nat_packet = None;
+                    events.push(RouterEvent::Dropped(packet));
+                } else {
+                    self.queues[self.nat_target].push_back((x, y));
+                    events.push(RouterEvent::NatSent(packet));
+                }
+            }
+        }
+        Ok(events)
+    }
+
+    // Runs rounds until f returns true for an event, and returns that event,
+    // or None if it doesn't happen within max_rounds.
+    pub fn run_until<F: FnMut(&RouterEvent) -> bool>(&mut self, max_rounds: usize, mut f: F) -> Result<Option<RouterEvent>, Error> {
+        for _ in 0..max_rounds {
+            if let Some(event) = self.round()?.into_iter().find(|e| f(e)) {
+                return Ok(Some(event));
+            }
+        }
+        Ok(None)
+    }
+}
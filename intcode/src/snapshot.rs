@@ -0,0 +1,251 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use crate::{read_from_string, Computer, ParseError, Word};
+
+// A snapshot holds mem, pc, relative_base and the input and output queues.
+// Watchpoints and tracers belong to whoever is running the machine, so they
+// aren't saved.
+//
+// The binary form is MAGIC followed by zigzag LEB128 varints: pc,
+// relative_base, then the length and contents of mem, input and output.
+// Machines with sparse memory add its start address and a list of
+// address/value pairs; older snapshots simply end after output.
+
+const MAGIC: &[u8; 4] = b"ICS1";
+const TEXT_HEADER: &str = "intcode snapshot 1";
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    BadMagic,
+    Truncated,
+    BadValue,
+    BadText { line: usize, reason: String },
+    Parse { line: usize, error: ParseError },
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "{}", e),
+            SnapshotError::BadMagic => write!(f, "not an intcode snapshot"),
+            SnapshotError::Truncated => write!(f, "snapshot is truncated"),
+            SnapshotError::BadValue => write!(f, "snapshot contains a value that's out of range"),
+            SnapshotError::BadText { line, reason } => write!(f, "line {}: {}", line, reason),
+            SnapshotError::Parse { line, error } => write!(f, "line {}: {}", line, error),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> Self {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            SnapshotError::Truncated
+        } else {
+            SnapshotError::Io(e)
+        }
+    }
+}
+
+// The encoding is done at 128 bits, which leaves the format room for
+// Computers with wider words.
+#[allow(clippy::useless_conversion)]
+fn write_varint<W: Write>(w: &mut W, value: Word) -> io::Result<()> {
+    let value = i128::from(value);
+    let mut v = ((value << 1) ^ (value >> 127)) as u128;
+    loop {
+        let byte = (v & 0x7f) as u8;
+        v >>= 7;
+        if v == 0 {
+            return w.write_all(&[byte]);
+        }
+        w.write_all(&[byte | 0x80])?;
+    }
+}
+
+fn read_varint<R: Read>(r: &mut R) -> Result<Word, SnapshotError> {
+    let mut v: u128 = 0;
+    let mut shift = 0;
+    loop {
+        let mut byte = [0u8];
+        r.read_exact(&mut byte)?;
+        if shift >= 128 {
+            return Err(SnapshotError::BadValue);
+        }
+        v |= ((byte[0] & 0x7f) as u128) << shift;
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    let value = ((v >> 1) as i128) ^ -((v & 1) as i128);
+    #[allow(clippy::useless_conversion)]
+    Word::try_from(value).map_err(|_| SnapshotError::BadValue)
+}
+
+fn write_list<'a, W: Write, I: ExactSizeIterator<Item = &'a Word>>(w: &mut W, values: I) -> io::Result<()> {
+    write_varint(w, values.len() as Word)?;
+    for v in values {
+        write_varint(w, *v)?;
+    }
+    Ok(())
+}
+
+fn read_list<R: Read>(r: &mut R) -> Result<Vec<Word>, SnapshotError> {
+    let len = read_varint(r)?;
+    if len < 0 {
+        return Err(SnapshotError::BadValue);
+    }
+    // Don't trust the length for the allocation; a corrupt file shouldn't be
+    // able to ask for terabytes up front.
+    let mut result = Vec::with_capacity(std::cmp::min(len as usize, 1 << 16));
+    for _ in 0..len {
+        result.push(read_varint(r)?);
+    }
+    Ok(result)
+}
+
+// Like read_varint, but a clean end of input gives None.
+fn read_optional_varint<R: Read>(r: &mut R) -> Result<Option<Word>, SnapshotError> {
+    let mut byte = [0u8];
+    if r.read(&mut byte)? == 0 {
+        return Ok(None);
+    }
+    read_varint(&mut io::Cursor::new(byte).chain(r)).map(Some)
+}
+
+fn to_usize(value: Word) -> Result<usize, SnapshotError> {
+    usize::try_from(value).map_err(|_| SnapshotError::BadValue)
+}
+
+fn restore_sparse(c: &mut Computer, start: Word, pairs: &[Word]) -> Result<(), SnapshotError> {
+    let start = to_usize(start)?;
+    if !pairs.len().is_multiple_of(2) {
+        return Err(SnapshotError::BadValue);
+    }
+    c.enable_sparse_memory(start);
+    for pair in pairs.chunks(2) {
+        let address = to_usize(pair[0])?;
+        if address < start.max(c.mem.len()) {
+            return Err(SnapshotError::BadValue);
+        }
+        c.write_mem(address, pair[1]).map_err(|_| SnapshotError::BadValue)?;
+    }
+    Ok(())
+}
+
+fn sparse_pairs(c: &Computer) -> Vec<Word> {
+    c.sparse_cells().into_iter().flat_map(|(a, v)| [a as Word, v]).collect()
+}
+
+fn line_field(name: &str, line: usize, text: &str) -> Result<(usize, String), SnapshotError> {
+    match text.strip_prefix(name) {
+        Some(rest) if rest.is_empty() || rest.starts_with(' ') => Ok((line, rest.trim().to_string())),
+        _ => Err(SnapshotError::BadText { line, reason: format!("expected {}", name) }),
+    }
+}
+
+fn join(values: impl Iterator<Item = Word>) -> String {
+    values.map(|v| v.to_string()).collect::<Vec<String>>().join(",")
+}
+
+impl Computer {
+    pub fn save<W: Write>(&self, mut w: W) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        write_varint(&mut w, self.pc as Word)?;
+        write_varint(&mut w, self.relative_base)?;
+        write_list(&mut w, self.mem.iter())?;
+        write_list(&mut w, self.input.iter())?;
+        write_list(&mut w, self.output.iter())?;
+        if let Some(start) = self.sparse_memory_start() {
+            write_varint(&mut w, start as Word)?;
+            write_list(&mut w, sparse_pairs(self).iter())?;
+        }
+        w.flush()
+    }
+
+    pub fn restore<R: Read>(mut r: R) -> Result<Self, SnapshotError> {
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+
+        let pc = to_usize(read_varint(&mut r)?)?;
+        let relative_base = read_varint(&mut r)?;
+
+        let mut c = Computer::new(read_list(&mut r)?);
+        c.pc = pc;
+        c.relative_base = relative_base;
+        c.input = read_list(&mut r)?.into();
+        c.output = read_list(&mut r)?.into();
+        if let Some(start) = read_optional_varint(&mut r)? {
+            restore_sparse(&mut c, start, &read_list(&mut r)?)?;
+        }
+        Ok(c)
+    }
+
+    pub fn save_to_path(&self, path: &str) -> io::Result<()> {
+        self.save(io::BufWriter::new(std::fs::File::create(path)?))
+    }
+
+    pub fn restore_from_path(path: &str) -> Result<Self, SnapshotError> {
+        Self::restore(io::BufReader::new(std::fs::File::open(path)?))
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{}\npc {}\nrelative_base {}\ninput {}\noutput {}\nmem {}\n",
+            TEXT_HEADER,
+            self.pc,
+            self.relative_base,
+            join(self.input.iter().copied()),
+            join(self.output.iter().copied()),
+            join(self.mem.iter().copied()));
+        if let Some(start) = self.sparse_memory_start() {
+            text += &format!("sparse_start {}\nsparse {}\n", start, join(sparse_pairs(self).into_iter()));
+        }
+        text
+    }
+
+    pub fn from_text(s: &str) -> Result<Self, SnapshotError> {
+        let mut lines = s.lines().enumerate().map(|(i, line)| (i + 1, line));
+        match lines.next() {
+            Some((_, TEXT_HEADER)) => {}
+            _ => return Err(SnapshotError::BadMagic),
+        }
+
+        let mut field = |name: &str| -> Result<(usize, String), SnapshotError> {
+            let (line, text) = lines.next().ok_or(SnapshotError::Truncated)?;
+            line_field(name, line, text)
+        };
+        let number = |(line, text): (usize, String)| {
+            text.parse::<Word>().map_err(|_| SnapshotError::BadText { line, reason: format!("bad number {:?}", text) })
+        };
+        let list = |(line, text): (usize, String)| {
+            read_from_string(&text).map_err(|error| SnapshotError::Parse { line, error })
+        };
+
+        let pc = to_usize(number(field("pc")?)?)?;
+        let relative_base = number(field("relative_base")?)?;
+        let input = list(field("input")?)?;
+        let output = list(field("output")?)?;
+        let mem = list(field("mem")?)?;
+
+        let mut c = Computer::new(mem);
+        c.pc = pc;
+        c.relative_base = relative_base;
+        c.input = input.into();
+        c.output = output.into();
+        if let Some((line, text)) = lines.next() {
+            let start = number(line_field("sparse_start", line, text)?)?;
+            let (line, text) = lines.next().ok_or(SnapshotError::Truncated)?;
+            let pairs = list(line_field("sparse", line, text)?)?;
+            restore_sparse(&mut c, start, &pairs)?;
+        }
+        Ok(c)
+    }
+}
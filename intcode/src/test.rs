@@ -184,4 +184,1304 @@ fn day9_case_3() {
     let mut c = Computer::new(vec!(104,1125899906842624,99));
     c.run().unwrap();
     assert_eq!(c.take_output()[0], 1125899906842624);
-}
\ No newline at end of file
+}
+
+#[test]
+fn errors_carry_pc_and_opcode() {
+    let mut c = Computer::new(vec!(1101,1,1,5,42,0));
+    assert_eq!(c.run().unwrap_err(), Error::BadOpcode { pc: 4, opcode: 42 });
+
+    let mut c = Computer::new(vec!(104,7,304,0,99));
+    assert_eq!(c.run().unwrap_err(), Error::BadMode { pc: 2, opcode: 304, param: 0, mode: 3 });
+    assert_eq!(c.take_output(), vec!(7));
+
+    let mut c = Computer::new(vec!(1101,1,1));
+    assert_eq!(c.run().unwrap_err(), Error::Truncated { pc: 0, opcode: 1101 });
+
+    // Modes past the ones an instruction uses have to be zero.
+    assert_eq!(decode(&[1000000001,0,0,0], 0), Err(Error::BadMode { pc: 0, opcode: 1000000001, param: 7, mode: 1 }));
+    assert_eq!(decode(&[11104,0], 0), Err(Error::BadMode { pc: 0, opcode: 11104, param: 1, mode: 1 }));
+    assert_eq!(decode(&[199], 0), Err(Error::BadMode { pc: 0, opcode: 199, param: 0, mode: 1 }));
+    assert_eq!(decode(&[-101], 0), Err(Error::BadOpcode { pc: 0, opcode: -101 }));
+
+    let mut c = Computer::new(vec!(1105,1,10));
+    assert_eq!(c.run().unwrap_err(), Error::BadPc { pc: 10 });
+}
+
+#[test]
+fn errors_carry_computed_address() {
+    let mut c = Computer::new(vec!(4,-3,99));
+    assert_eq!(c.run().unwrap_err(), Error::BadLoadAddress { pc: 0, address: -3 });
+
+    let mut c = Computer::new(vec!(109,-5,21101,1,1,2,99));
+    assert_eq!(c.run().unwrap_err(), Error::BadStoreAddress { pc: 2, address: -3 });
+
+    let mut c = Computer::new(vec!(1105,1,-1));
+    assert_eq!(c.run().unwrap_err(), Error::BadJumpTarget { pc: 0, target: -1 });
+}
+
+#[test]
+fn error_display() {
+    let e = Error::BadMode { pc: 2, opcode: 30004, param: 0, mode: 3 };
+    assert_eq!(e.to_string(), "2: unrecognized mode 3 for parameter 0 of 30004");
+    assert_eq!(e.pc(), 2);
+}
+
+#[test]
+fn parse_tolerates_whitespace_and_comments() {
+    assert_eq!(read_from_string("1,0,0,0,99\n").unwrap(), vec!(1,0,0,0,99));
+    assert_eq!(read_from_string(" 1, 0,\n 0 ,0,\r\n99,\n").unwrap(), vec!(1,0,0,0,99));
+    assert_eq!(read_from_string("# header\n1,0,0,0, # add\n99 # exit\n").unwrap(), vec!(1,0,0,0,99));
+    assert_eq!(read_from_string("").unwrap(), vec!());
+}
+
+#[test]
+fn parse_strict_rejects_comments() {
+    assert_eq!(read_from_string_strict(" 1, 0,\n 0 ,0,\r\n99,\n").unwrap(), vec!(1,0,0,0,99));
+
+    let e = read_from_string_strict("1,0,0,0, # add\n99").unwrap_err();
+    assert_eq!(e, ParseError { index: 4, offset: 11, kind: ParseErrorKind::MissingComma });
+    let e = read_from_string_strict("#1,0,0,0,99").unwrap_err();
+    assert_eq!((e.index, e.offset), (0, 0));
+    assert!(matches!(e.kind, ParseErrorKind::BadNumber(_, _)));
+}
+
+#[test]
+fn parse_reports_bad_values() {
+    let e = read_from_string("1,0,0x,0,99").unwrap_err();
+    assert_eq!((e.index, e.offset), (2, 4));
+    match e.kind {
+        ParseErrorKind::BadNumber(token, _) => assert_eq!(token, "0x"),
+        kind => panic!("unexpected error {:?}", kind),
+    }
+
+    let e = read_from_string("1,0,,0,99").unwrap_err();
+    assert_eq!(e, ParseError { index: 2, offset: 4, kind: ParseErrorKind::EmptyValue });
+
+    let e = read_from_string("1,0 0,0,99").unwrap_err();
+    assert_eq!(e, ParseError { index: 1, offset: 4, kind: ParseErrorKind::MissingComma });
+    assert_eq!(e.to_string(), "value 1 at byte 4: expected a comma");
+
+    assert!(read_from_string("1,99999999999999999999").is_err());
+    assert!(read_from_string("1,9999999999999999999999999999999999999999").is_err());
+    assert!(Computer::load_from_string("1,0,0,0,99,,").is_err());
+}
+
+#[test]
+fn encode_round_trips_through_decode() {
+    let programs = vec!(
+        vec!(1,2,3,4), vec!(1002,4,3,4), vec!(21101,5,6,-1), vec!(3,9), vec!(203,1),
+        vec!(104,7), vec!(1105,1,0), vec!(2106,0,8), vec!(1107,1,2,3), vec!(8,1,2,3),
+        vec!(109,19), vec!(99));
+    for p in programs {
+        let inst = decode(&p[..], 0).unwrap();
+        assert_eq!(inst.len(), p.len());
+        assert_eq!(inst.encode(), p);
+    }
+}
+
+#[test]
+fn disassemble_marks_code_data_and_labels() {
+    // IN [12]; L2: OUT [12]; [12] = ADD [12],-1; IF [12] GOTO L2; EXIT; data
+    let p = vec!(3,12,4,12,1001,12,-1,12,1005,12,2,99,0,77);
+    let listing = disassemble(&p);
+
+    let addresses: Vec<usize> = listing.iter().map(|l| l.address).collect();
+    assert_eq!(addresses, vec!(0,2,4,8,11,12));
+    assert!(listing[..5].iter().all(|l| !l.is_data()));
+    assert!(listing[5].is_data());
+    assert_eq!(listing[5].words, vec!(0,77));
+
+    assert_eq!(listing[1].label, Some("L2".to_string()));
+    assert_eq!(listing[3].target_label, Some("L2".to_string()));
+
+    let text: Vec<String> = listing.iter().map(|l| l.to_string()).collect();
+    assert_eq!(text[1], "     2  4,12                     L2:      OUT pos:12");
+    assert_eq!(text[3], "     8  1005,12,2                         JT pos:12 imm:L2");
+    assert_eq!(text[5], "    12  0,77                              .data 0 77");
+}
+
+#[test]
+fn disassemble_skips_words_jumped_over() {
+    // GOTO 5; data; EXIT
+    let p = vec!(1105,1,5,1234,-1,99);
+    let listing = disassemble(&p);
+    assert_eq!(listing.len(), 3);
+    assert_eq!(listing[0].inst, Some(Inst::JumpIfTrue(InParam::Immediate(1), InParam::Immediate(5))));
+    assert!(listing[1].is_data());
+    assert_eq!(listing[1].words, vec!(1234,-1));
+    assert_eq!(listing[2].inst, Some(Inst::Exit));
+    assert_eq!(listing[2].label, Some("L5".to_string()));
+}
+
+#[test]
+fn debugger_breakpoints() {
+    use crate::debugger::*;
+
+    // count down from 3, outputting each value
+    let p = vec!(1101,3,0,14,4,14,1001,14,-1,14,1005,14,4,99,0);
+    let mut d = Debugger::new(Computer::new(p));
+    d.add_breakpoint(Breakpoint::new(Some(10), None));
+
+    assert_eq!(d.cont(), Stop::Breakpoint(0));
+    assert_eq!(d.computer.pc, 10);
+    assert_eq!(d.computer.take_output(), vec!(3));
+    assert_eq!(d.cont(), Stop::Breakpoint(0));
+    assert_eq!(d.computer.take_output(), vec!(2));
+
+    assert!(d.remove_breakpoint(0));
+    assert_eq!(d.cont(), Stop::Halted);
+    assert_eq!(d.computer.take_output(), vec!(1));
+}
+
+#[test]
+fn debugger_conditional_breakpoints() {
+    use crate::debugger::*;
+
+    let p = vec!(1101,3,0,14,4,14,1001,14,-1,14,1005,14,4,99,0);
+    let mut d = Debugger::new(Computer::new(p.clone()));
+    d.run_command(&parse_command("break if [14] == 1").unwrap());
+    assert_eq!(d.cont(), Stop::Breakpoint(0));
+    assert_eq!(d.computer.pc, 10);
+    assert_eq!(d.computer.take_output(), vec!(3,2));
+
+    let mut d = Debugger::new(Computer::new(p));
+    d.run_command(&parse_command("break 4 if [14] < 3").unwrap());
+    assert_eq!(d.cont(), Stop::Breakpoint(0));
+    assert_eq!(d.computer.take_output(), vec!(3));
+}
+
+#[test]
+fn debugger_conditions_fire_when_they_become_true() {
+    use crate::debugger::*;
+
+    let p = vec!(1101,3,0,14,4,14,1001,14,-1,14,1005,14,4,99,0);
+    let mut d = Debugger::new(Computer::new(p));
+    d.run_command(&parse_command("break if [14] == 3").unwrap());
+    assert_eq!(d.cont(), Stop::Breakpoint(0));
+    assert_eq!(d.computer.pc, 4);
+    // Still true after the next instruction, but it didn't just become true.
+    assert_eq!(d.cont(), Stop::Halted);
+
+    assert!(parse_command("print [18446744073709551615]").is_err());
+    assert!(parse_command("watch [18446744073709551615..0]").is_err());
+
+    let mut c = Computer::new(vec!(99));
+    c.enable_sparse_memory(1000);
+    c.write_mem(5000, 104).unwrap();
+    c.write_mem(5001, 7).unwrap();
+    let d = Debugger::new(c);
+    assert_eq!(d.list(5000, 1), vec!(format!("   {:>6}: {}", 5000, Inst::Output(InParam::Immediate(7)))));
+}
+
+#[test]
+fn debugger_commands() {
+    use crate::debugger::*;
+
+    let mut d = Debugger::new(Computer::new(vec!(3,7,4,7,1105,1,0,0)));
+    assert_eq!(parse_command("step 2").unwrap(), Command::Step(2));
+    assert_eq!(parse_command("print [1..3]").unwrap(), Command::Print(Location::Mem(1, 3)));
+    assert!(parse_command("frobnicate").is_err());
+    assert!(parse_command("break if [1..3] == 0").is_err());
+    assert_eq!(parse_command("print [0..1000000000]"), Err("ranges can be at most 4096 cells".to_string()));
+    assert!(parse_command("set [0..1000000000] 1").is_err());
+    assert!(parse_command("print [0..4096]").is_ok());
+
+    assert_eq!(d.run_command(&Command::Step(1)), "waiting for input\n=>      0: IN pos:7");
+    d.run_command(&parse_command("input 42 43").unwrap());
+    assert_eq!(d.run_command(&Command::Next), "=>      2: OUT pos:7");
+    assert_eq!(d.run_command(&parse_command("p [7]").unwrap()), "[7] = 42");
+    d.run_command(&parse_command("set [7] 5").unwrap());
+    assert_eq!(d.run_command(&Command::Step(1)), "output: 5\n=>      4: JT imm:1 imm:0");
+    d.run_command(&parse_command("set pc 2").unwrap());
+    assert_eq!(d.run_command(&parse_command("p pc").unwrap()), "pc = 2");
+    assert_eq!(d.run_command(&parse_command("p input").unwrap()), "input = [43]");
+}
+
+#[test]
+fn watchpoint_pauses_on_write() {
+    // [9] = ADD [9],1 three times, then EXIT
+    let mut c = Computer::new(vec!(1001,9,1,9,1001,9,1,9,99,5));
+    c.watch(9, WatchKind::Write, WatchAction::Pause);
+
+    let result = c.run().unwrap();
+    assert!(result.paused);
+    assert!(!result.done);
+    assert_eq!(result.watch_hits, vec!(WatchEvent { pc: 0, address: 9, access: Access::Write, old_value: 5, new_value: 6 }));
+    assert_eq!(c.pc, 4);
+
+    let result = c.run().unwrap();
+    assert_eq!(result.watch_hits[0].pc, 4);
+    assert_eq!(result.watch_hits[0].new_value, 7);
+
+    let result = c.run().unwrap();
+    assert!(result.done);
+    assert!(!result.paused);
+}
+
+#[test]
+fn watchpoint_callbacks() {
+    use std::sync::{Arc, Mutex};
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorder = events.clone();
+
+    let mut c = Computer::new(vec!(1001,9,1,9,4,9,4,10,99,5));
+    let id = c.watch(9, WatchKind::ReadWrite, WatchAction::Callback(Arc::new(move |e: &WatchEvent| {
+        recorder.lock().unwrap().push(*e);
+    })));
+    c.watch(10, WatchKind::Write, WatchAction::Pause);
+
+    let result = c.run().unwrap();
+    assert!(result.done);
+    assert_eq!(c.take_output(), vec!(6, 0));
+
+    let events = events.lock().unwrap();
+    let summary: Vec<(usize, Access, Word, Word)> = events.iter()
+        .map(|e| (e.pc, e.access, e.old_value, e.new_value))
+        .collect();
+    assert_eq!(summary, vec!((0, Access::Read, 5, 5), (0, Access::Write, 5, 6), (4, Access::Read, 6, 6)));
+
+    assert!(c.unwatch(id));
+    assert!(!c.unwatch(id));
+}
+
+#[test]
+fn debugger_watchpoints() {
+    use crate::debugger::*;
+
+    let p = vec!(1101,3,0,14,4,14,1001,14,-1,14,1005,14,4,99,0);
+    let mut d = Debugger::new(Computer::new(p));
+    assert_eq!(parse_command("watch [14]").unwrap(), Command::Watch(14, WatchKind::Write));
+    d.run_command(&parse_command("watch [14]").unwrap());
+
+    assert_eq!(d.run_command(&Command::Continue), "Write of [14] at pc 0: 0 -> 3\n=>      4: OUT pos:14");
+    assert_eq!(d.cont(), Stop::Watchpoint(WatchEvent { pc: 6, address: 14, access: Access::Write, old_value: 3, new_value: 2 }));
+}
+
+#[test]
+fn trace_to_writer() {
+    let mut c = Computer::new(vec!(3,9,1002,9,2,9,4,9,99,0));
+    c.send_input(21);
+    let tracer = c.trace_with(WriteTracer::new(Vec::new()));
+    c.run().unwrap();
+
+    let text = String::from_utf8(tracer.lock().unwrap().writer.clone()).unwrap();
+    assert_eq!(text, "\
+0: pos:9 = INPUT 21
+2: pos:9 = MULT pos:9 (21) imm:2 (2)
+6: OUTPUT pos:9 (42)
+8: EXIT
+");
+}
+
+#[test]
+fn trace_ring_buffer_after_error() {
+    let mut c = Computer::new(vec!(1101,1,1,11,1101,2,2,11,4,-1,99,0));
+    let ring = c.trace_with(RingTracer::new(1));
+    assert!(c.run().is_err());
+
+    let events = ring.lock().unwrap().events();
+    assert_eq!(events, vec!(TraceEvent {
+        pc: 4,
+        inst: Inst::Add(InParam::Immediate(2), InParam::Immediate(2), OutParam::Position(11)),
+        operands: [Some(2), Some(2)],
+        store: Some((11, 4)),
+        relative_base: 0,
+    }));
+}
+
+#[test]
+fn trace_json_lines() {
+    let mut c = Computer::new(vec!(109,5,204,-5,99));
+    let tracer = c.trace_with(JsonTracer::new(Vec::new()));
+    c.run().unwrap();
+
+    let text = String::from_utf8(tracer.lock().unwrap().writer.clone()).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines[0], r#"{"pc":0,"inst":"ARB imm:5","operands":[5],"store":null,"relative_base":5}"#);
+    assert_eq!(lines[1], r#"{"pc":2,"inst":"OUT rel:-5","operands":[109],"store":null,"relative_base":5}"#);
+}
+
+#[test]
+fn snapshot_round_trip() {
+    // echoes two inputs, doubled, using the relative base
+    let mut c = Computer::new(vec!(109,100,203,0,1002,100,2,100,4,100,1105,1,2));
+    c.send_input(-7);
+    c.send_input(1 << 40);
+    c.send_input(3);
+    for _ in 0..6 {
+        c.step().unwrap();
+    }
+    assert_eq!(c.output, vec!(-14));
+
+    let mut bytes = Vec::new();
+    c.save(&mut bytes).unwrap();
+    let binary = Computer::restore(&bytes[..]).unwrap();
+    let text = Computer::from_text(&c.to_text()).unwrap();
+
+    for mut copy in [binary, text] {
+        assert_eq!(copy.mem, c.mem);
+        assert_eq!(copy.pc, c.pc);
+        assert_eq!(copy.relative_base, c.relative_base);
+        assert_eq!(copy.input, c.input);
+        assert_eq!(copy.output, c.output);
+
+        for _ in 0..4 {
+            copy.step().unwrap();
+        }
+        assert_eq!(copy.take_output(), vec!(-14, 1 << 41));
+    }
+}
+
+#[test]
+fn snapshot_text_format() {
+    let mut c = Computer::new(vec!(3,0,99));
+    c.send_input(4);
+    c.relative_base = -2;
+    assert_eq!(c.to_text(), "intcode snapshot 1\npc 0\nrelative_base -2\ninput 4\noutput \nmem 3,0,99\n");
+}
+
+#[test]
+fn snapshot_errors() {
+    let c = Computer::new(vec!(1,0,0,0,99));
+    let mut bytes = Vec::new();
+    c.save(&mut bytes).unwrap();
+
+    match Computer::restore(&bytes[..bytes.len() - 1]) {
+        Err(SnapshotError::Truncated) => {}
+        other => panic!("unexpected {:?}", other),
+    }
+    match Computer::restore(&b"nope"[..]) {
+        Err(SnapshotError::BadMagic) => {}
+        other => panic!("unexpected {:?}", other),
+    }
+    match Computer::from_text("intcode snapshot 1\npc 0\nrelative_base 0\ninput\noutput\nmem 1,x") {
+        Err(SnapshotError::Parse { line: 6, .. }) => {}
+        other => panic!("unexpected {:?}", other),
+    }
+    match Computer::from_text("intcode snapshot 1\npc 0\nmem 1") {
+        Err(SnapshotError::BadText { line: 3, .. }) => {}
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn history_rewind() {
+    // [14] = 3; L4: OUT [14]; [14] = ADD [14],-1; IF [14] GOTO L4; EXIT
+    let p = vec!(1101,3,0,14,4,14,1001,14,-1,14,1005,14,4,99,0);
+    let mut c = Computer::new(p.clone());
+    c.enable_history(None);
+    c.run().unwrap();
+    assert_eq!(c.history_position(), 10);
+    assert_eq!(c.output, vec!(3,2,1));
+
+    assert_eq!(c.rewind(3), 3);
+    assert_eq!(c.pc, 4);
+    assert_eq!(c.mem[14], 1);
+    assert_eq!(c.output, vec!(3,2));
+
+    assert!(c.rewind_to_pc(6));
+    assert_eq!(c.pc, 6);
+    assert_eq!(c.mem[14], 2);
+    assert!(!c.rewind_to_pc(99));
+
+    assert_eq!(c.rewind(100), 5);
+    assert_eq!(c.pc, 0);
+    assert_eq!(c.mem, p);
+    assert!(c.output.is_empty());
+}
+
+#[test]
+fn history_rewind_restores_fuel() {
+    let p = vec!(1101,3,0,14,4,14,1001,14,-1,14,1005,14,4,99,0);
+    let mut c = Computer::new(p);
+    c.set_fuel(Some(4));
+    c.enable_history(None);
+    assert_eq!(c.run().unwrap_err(), Error::OutOfFuel { pc: 4 });
+    assert_eq!(c.fuel(), Some(0));
+
+    assert_eq!(c.rewind(4), 4);
+    assert_eq!(c.fuel(), Some(4));
+    assert_eq!(c.instructions_executed(), 0);
+    assert_eq!(c.run().unwrap_err(), Error::OutOfFuel { pc: 4 });
+    assert_eq!(c.output, vec!(3));
+    assert_eq!(c.instructions_executed(), 4);
+}
+
+#[test]
+fn history_rewind_input_and_growth() {
+    let mut c = Computer::new(vec!(3,10,99));
+    c.send_input(5);
+    c.enable_history(Some(1));
+    c.run().unwrap();
+    assert_eq!(c.mem.len(), 11);
+
+    assert_eq!(c.rewind(5), 1);
+    assert_eq!(c.mem, vec!(3,10,99));
+    assert_eq!(c.input, vec!(5));
+}
+
+#[test]
+fn history_replay() {
+    let mut c = Computer::new(vec!(3,13,3,14,1,13,14,15,4,15,1105,1,0,0,0,0));
+    c.enable_history(None);
+    for input in [1, 2, 10, 20] {
+        c.send_input(input);
+        while !c.step().unwrap().input_needed {}
+    }
+    assert_eq!(c.take_output(), vec!(3, 30));
+
+    let replayed = c.replay_to(5).unwrap();
+    assert_eq!(replayed.history_position(), 5);
+    assert_eq!(replayed.pc, 0);
+    assert_eq!(replayed.output, vec!(3));
+    assert_eq!(replayed.input, vec!(10, 20));
+    assert!(c.replay_to(100).is_none());
+
+    // A deadline that has passed doesn't stop the replay.
+    let mut c = Computer::new(vec!(104,1,104,2,99));
+    c.set_deadline(Some(std::time::Instant::now()));
+    c.enable_history(None);
+    c.set_deadline(None);
+    c.run().unwrap();
+    assert_eq!(c.replay_to(2).unwrap().output, vec!(1, 2));
+}
+
+#[test]
+fn debugger_steps_back() {
+    use crate::debugger::*;
+
+    let p = vec!(1101,3,0,14,4,14,1001,14,-1,14,1005,14,4,99,0);
+    let mut d = Debugger::new(Computer::new(p));
+    d.step(4);
+    assert_eq!(d.computer.mem[14], 2);
+    assert_eq!(d.run_command(&parse_command("back 2").unwrap()), "stepped back 2 instructions\n=>      6: ADD pos:14 imm:-1 pos:14");
+    assert_eq!(d.computer.mem[14], 3);
+}
+
+#[test]
+fn fuel_stops_infinite_loops() {
+    let mut c = Computer::new(vec!(1105,1,0));
+    c.set_fuel(Some(1000));
+    assert_eq!(c.run().unwrap_err(), Error::OutOfFuel { pc: 0 });
+    assert_eq!(c.instructions_executed(), 1000);
+    assert_eq!(c.fuel(), Some(0));
+
+    c.set_fuel(Some(5));
+    assert!(c.run().is_err());
+    assert_eq!(c.instructions_executed(), 1005);
+}
+
+#[test]
+fn fuel_is_enough_for_terminating_programs() {
+    let mut c = Computer::new(vec!(1,0,0,0,99));
+    c.set_fuel(Some(1));
+    assert!(c.run().unwrap().done);
+    assert_eq!(c.fuel(), Some(0));
+    assert_eq!(c.instructions_executed(), 1);
+}
+
+#[test]
+fn deadline_stops_infinite_loops() {
+    let mut c = Computer::new(vec!(1105,1,0));
+    c.set_time_limit(std::time::Duration::from_millis(10));
+    assert_eq!(c.run().unwrap_err(), Error::DeadlineExceeded { pc: 0 });
+    assert!(c.instructions_executed() > 0);
+}
+
+#[test]
+fn memory_limit_stops_huge_stores() {
+    let mut c = Computer::new(vec!(1101,1,1,1000000000000,99));
+    assert_eq!(c.run().unwrap_err(), Error::MemoryLimit { pc: 0, address: 1000000000000 });
+    assert_eq!(c.mem.len(), 5);
+
+    let mut c = Computer::new(vec!(1101,1,1,20,99));
+    c.set_memory_limit(Some(20));
+    assert_eq!(c.run().unwrap_err(), Error::MemoryLimit { pc: 0, address: 20 });
+    c.set_memory_limit(Some(21));
+    assert!(c.run().unwrap().done);
+
+    let mut c = Computer::new(vec!(99));
+    assert_eq!(c.write_mem(usize::MAX, 1).unwrap_err(), Error::MemoryLimit { pc: 0, address: usize::MAX });
+    c.enable_decode_cache();
+    assert_eq!(c.write_mem(usize::MAX, 1).unwrap_err(), Error::MemoryLimit { pc: 0, address: usize::MAX });
+}
+
+#[test]
+fn failed_input_store_keeps_the_input() {
+    let mut c = Computer::new(vec!(3,1000000000000,99));
+    c.send_input(7);
+    assert_eq!(c.run().unwrap_err(), Error::MemoryLimit { pc: 0, address: 1000000000000 });
+    assert_eq!(c.input, vec!(7));
+
+    let mut c = Computer::new(vec!(3,-1,99));
+    c.enable_decode_cache();
+    c.send_input(7);
+    c.send_input(8);
+    assert_eq!(c.run().unwrap_err(), Error::BadStoreAddress { pc: 0, address: -1 });
+    assert_eq!(c.input, vec!(7, 8));
+}
+
+#[test]
+fn sparse_memory() {
+    let mut c = Computer::new(vec!(1101,5,6,1000000000000,4,1000000000000,99));
+    c.enable_sparse_memory(1000);
+    c.enable_history(None);
+    assert!(c.run().unwrap().done);
+    assert_eq!(c.take_output(), vec!(11));
+    assert_eq!(c.mem.len(), 7);
+    assert_eq!(c.sparse_cells(), vec!((1000000000000, 11)));
+    assert_eq!(c.memory_used(), 8);
+
+    let restored = Computer::from_text(&c.to_text()).unwrap();
+    assert_eq!(restored.sparse_cells(), c.sparse_cells());
+    let mut bytes = Vec::new();
+    c.save(&mut bytes).unwrap();
+    let restored = Computer::restore(&bytes[..]).unwrap();
+    assert_eq!(restored.sparse_memory_start(), Some(1000));
+    assert_eq!(restored.sparse_cells(), c.sparse_cells());
+
+    assert_eq!(c.rewind(2), 2);
+    assert_eq!(c.sparse_cells(), vec!());
+
+    c.set_memory_limit(Some(7));
+    assert_eq!(c.run().unwrap_err(), Error::MemoryLimit { pc: 0, address: 1000000000000 });
+}
+
+#[test]
+fn sparse_memory_at_the_top_of_the_address_space() {
+    let mut c = Computer::new(vec!(99));
+    c.enable_sparse_memory(1000);
+    c.pc = usize::MAX;
+    assert_eq!(c.run().unwrap_err(), Error::BadPc { pc: usize::MAX });
+    c.pc = usize::MAX - 1;
+    c.write_mem(usize::MAX - 1, 1101).unwrap();
+    assert_eq!(c.run().unwrap_err(), Error::Truncated { pc: usize::MAX - 1, opcode: 1101 });
+}
+
+#[test]
+fn sparse_memory_runs_code() {
+    let mut c = Computer::new(vec!(1101,0,99,5000,1105,1,5000));
+    c.enable_sparse_memory(100);
+    assert!(c.run().unwrap().done);
+    assert_eq!(c.pc, 5000);
+
+    let mut c = Computer::new(vec!(1105,1,5000));
+    c.enable_sparse_memory(100);
+    assert_eq!(c.run().unwrap_err(), Error::BadOpcode { pc: 5000, opcode: 0 });
+}
+
+#[test]
+fn overflow_policy() {
+    let p = vec!(1101,Word::MAX,1,7,4,7,99,0);
+    let mut c = Computer::new(p.clone());
+    assert_eq!(c.run().unwrap_err(), Error::Overflow { pc: 0, lhs: Word::MAX, rhs: 1 });
+
+    let mut c = Computer::new(p.clone());
+    c.set_overflow_policy(OverflowPolicy::Wrapping);
+    c.run().unwrap();
+    assert_eq!(c.take_output(), vec!(Word::MIN));
+
+    let mut c = Computer::new(p);
+    c.set_overflow_policy(OverflowPolicy::Saturating);
+    c.run().unwrap();
+    assert_eq!(c.take_output(), vec!(Word::MAX));
+
+    let mut c = Computer::new(vec!(1102,Word::MIN / 2 - 1,2,7,99,0,0,0));
+    c.set_overflow_policy(OverflowPolicy::Saturating);
+    c.run().unwrap();
+    assert_eq!(c.mem[7], Word::MIN);
+    c.pc = 0;
+    c.set_overflow_policy(OverflowPolicy::Error);
+    assert_eq!(c.run().unwrap_err(), Error::Overflow { pc: 0, lhs: Word::MIN / 2 - 1, rhs: 2 });
+}
+
+#[test]
+fn relative_base_overflow() {
+    let mut c = Computer::new(vec!(109,Word::MAX,109,1,99));
+    c.set_overflow_policy(OverflowPolicy::Wrapping);
+    assert_eq!(c.run().unwrap_err(), Error::Overflow { pc: 2, lhs: Word::MAX, rhs: 1 });
+
+    let mut c = Computer::new(vec!(109,Word::MAX,204,1,99));
+    assert_eq!(c.run().unwrap_err(), Error::Overflow { pc: 2, lhs: Word::MAX, rhs: 1 });
+}
+
+#[test]
+fn i128_words() {
+    let p = read_words::<i128>("1102,170141183460469231731687303715884105727,1,7,4,7,99,0").unwrap();
+    let mut c = Computer::with_memory(p);
+    c.run().unwrap();
+    assert_eq!(c.take_output(), vec!(i128::MAX));
+
+    let mut c = Computer::with_memory(read_words::<i128>("1102,170141183460469231731687303715884105727,2,7,99,0").unwrap());
+    assert_eq!(c.run().unwrap_err(), Error::Overflow { pc: 0, lhs: i128::MAX, rhs: 2 });
+    assert!(read_words::<i128>("1,170141183460469231731687303715884105728").is_err());
+}
+
+#[cfg(feature = "bigint")]
+#[test]
+fn bigint_words() {
+    let big: BigInt = "1000000000000000000000000000000000000000".parse().unwrap();
+    let p = read_words::<BigInt>("1102,1000000000000000000000000000000000000000,-3,7,4,7,99,0").unwrap();
+    assert_eq!(p[1], big);
+
+    let mut c = Computer::with_memory(p);
+    c.run().unwrap();
+    assert_eq!(c.take_output(), vec!(big * -3));
+
+    // Still an error for things that aren't numbers at all.
+    assert!(read_words::<BigInt>("1,2x").is_err());
+    assert!(read_words::<BigInt>("1,99999999999999999999999x").is_err());
+}
+
+struct Script {
+    inputs: VecDeque<Word>,
+    outputs: Vec<Word>,
+}
+
+impl IoDevice for Script {
+    fn read(&mut self) -> Option<Word> {
+        self.inputs.pop_front()
+    }
+
+    fn write(&mut self, value: Word) {
+        self.outputs.push(value);
+    }
+}
+
+#[test]
+fn run_with_device() {
+    // Echoes its input until it reads zero.
+    let p = vec!(3,9,4,9,1005,9,0,99,0,0);
+    let mut c = Computer::new(p.clone());
+    let mut device = Script { inputs: vec!(3, 2, 0).into(), outputs: vec!() };
+    assert!(c.run_with(&mut device).unwrap().done);
+    assert_eq!(device.outputs, vec!(3, 2, 0));
+
+    let mut c = Computer::new(p);
+    let mut device = Script { inputs: vec!(5).into(), outputs: vec!() };
+    assert!(c.run_with(&mut device).unwrap().input_needed);
+    assert_eq!(device.outputs, vec!(5));
+    device.inputs.push_back(0);
+    assert!(c.run_with(&mut device).unwrap().done);
+    assert_eq!(device.outputs, vec!(5, 0));
+}
+
+#[test]
+fn resume_reports_events() {
+    // Outputs its input doubled, twice per input, until it reads zero.
+    let p = vec!(3,15,1002,15,2,16,4,16,4,16,1005,15,0,99,0,0,0);
+    let mut c = Computer::new(p);
+    assert_eq!(c.resume(), Ok(Event::NeedsInput));
+    c.send_input(4);
+    assert_eq!(c.resume(), Ok(Event::Output(8)));
+    assert_eq!(c.resume(), Ok(Event::Output(8)));
+    assert_eq!(c.resume(), Ok(Event::NeedsInput));
+    c.send_input(0);
+    assert_eq!(c.run_until_halt(), Ok(Ok(vec!(0, 0))));
+    assert_eq!(c.resume(), Ok(Event::Halted));
+
+    let mut c = Computer::new(vec!(1,0,0,0,1105,1,0));
+    c.watch(0, WatchKind::Write, WatchAction::Pause);
+    match c.resume() {
+        Ok(Event::Paused(hits)) => assert_eq!(hits[0].new_value, 2),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn run_until_output() {
+    let p = vec!(3,15,1002,15,2,16,4,16,4,16,1005,15,0,99,0,0,0);
+    let mut c = Computer::new(p);
+    c.send_input(3);
+    c.send_input(5);
+    assert_eq!(c.run_until_output(3), Ok(Ok(vec!(6, 6, 10))));
+    assert_eq!(c.run_until_output(2), Ok(Err(Stopped::InputNeeded)));
+    // The output that was produced is still there.
+    assert_eq!(c.output, vec!(10));
+    assert_eq!(c.run_until_input(), Ok(Ok(vec!(10))));
+
+    c.send_input(0);
+    assert_eq!(c.run_until_output(3), Ok(Err(Stopped::Halted)));
+    assert_eq!(c.take_output(), vec!(0, 0));
+    assert_eq!(c.run_until_input(), Ok(Err(Stopped::Halted)));
+
+    let mut c = Computer::new(vec!(3,0,99));
+    assert_eq!(c.run_until_halt(), Ok(Err(Stopped::InputNeeded)));
+    assert!(c.run().unwrap().input_needed);
+}
+
+#[test]
+fn threaded_feedback_loop() {
+    use crate::threaded::Network;
+
+    let p = read_from_string("3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5").unwrap();
+    let mut network = Network::new();
+    let amps: Vec<usize> = [9, 8, 7, 6, 5].iter().map(|&phase| {
+        let mut c = Computer::new(p.clone());
+        c.send_input(phase);
+        network.add(c)
+    }).collect();
+    for i in 0..amps.len() {
+        network.connect(amps[i], amps[(i + 1) % amps.len()]);
+    }
+    let input = network.input(amps[0]);
+    let output = network.output(amps[4]);
+    let running = network.start();
+    input.send(0);
+    drop(input);
+
+    let computers = running.join().unwrap();
+    assert_eq!(computers.len(), 5);
+    assert!(computers.iter().all(|(_, stopped)| *stopped == Stopped::Halted));
+    assert_eq!(output.try_iter().last(), Some(139629729));
+}
+
+#[test]
+fn threaded_deadlock() {
+    use crate::threaded::Network;
+
+    let mut network = Network::new();
+    let a = network.add(Computer::new(vec!(3,0,4,0,99)));
+    let b = network.add(Computer::new(vec!(3,0,4,0,99)));
+    network.connect(a, b).connect(b, a);
+    let computers = network.start().join().unwrap();
+    assert!(computers.iter().all(|(c, stopped)| *stopped == Stopped::Deadlock && c.pc == 0));
+}
+
+#[test]
+fn threaded_spawn() {
+    let (input, output, running) = threaded::spawn(Computer::new(vec!(3,9,4,9,1005,9,0,99,0,0)));
+    input.send(5);
+    assert_eq!(output.recv(), Ok(5));
+    input.send(7);
+    assert_eq!(output.recv(), Ok(7));
+
+    // Nobody can send it anything once the input is gone.
+    drop(input);
+    assert_eq!(running.join().unwrap()[0].1, Stopped::Deadlock);
+
+    let (input, output, running) = threaded::spawn(Computer::new(vec!(3,9,4,9,1005,9,0,99,0,0)));
+    input.send(0);
+    let (c, stopped) = &running.join().unwrap()[0];
+    assert_eq!((c.pc, *stopped), (7, Stopped::Halted));
+    assert_eq!(output.recv(), Ok(0));
+    assert!(!input.send(1));
+}
+
+#[cfg(feature = "async")]
+fn block_on<F: std::future::Future>(f: F) -> F::Output {
+    use std::task::{Context, Poll, Wake, Waker};
+
+    struct Noop;
+    impl Wake for Noop {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    let waker = Waker::from(Arc::new(Noop));
+    let mut cx = Context::from_waker(&waker);
+    let mut f = Box::pin(f);
+    loop {
+        if let Poll::Ready(value) = f.as_mut().poll(&mut cx) {
+            return value;
+        }
+    }
+}
+
+// Makes the reader wait once before each value.
+#[cfg(feature = "async")]
+struct SlowInput {
+    values: VecDeque<Word>,
+    ready: bool,
+}
+
+#[cfg(feature = "async")]
+impl futures_core::Stream for SlowInput {
+    type Item = Word;
+
+    fn poll_next(mut self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<Option<Word>> {
+        if !self.ready {
+            self.ready = true;
+            cx.waker().wake_by_ref();
+            return std::task::Poll::Pending;
+        }
+        self.ready = false;
+        std::task::Poll::Ready(self.values.pop_front())
+    }
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn async_computer() {
+    use futures_core::Stream;
+
+    let p = vec!(3,9,4,9,1005,9,0,99,0,0);
+    let input = SlowInput { values: vec!(4, 9, 0).into(), ready: false };
+    let mut driver = AsyncComputer::new(Computer::new(p.clone()), input);
+    assert_eq!(block_on(driver.run_to_end()), Ok(Ok(vec!(4, 9, 0))));
+    assert_eq!(driver.computer().pc, 7);
+    assert_eq!(driver.stopped(), Some(Stopped::Halted));
+
+    let input = SlowInput { values: vec!(4).into(), ready: false };
+    let mut driver = AsyncComputer::new(Computer::new(p), input);
+    let mut next = || block_on(std::future::poll_fn(|cx| std::pin::Pin::new(&mut driver).poll_next(cx)));
+    assert_eq!(next(), Some(Ok(4)));
+    assert_eq!(next(), None);
+    assert_eq!(next(), None);
+    assert_eq!(driver.stopped(), Some(Stopped::InputNeeded));
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn async_computer_on_tokio() {
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    runtime.block_on(async {
+        let (input, mut output, handle) = async_driver::tokio::spawn(Computer::new(vec!(3,9,4,9,1005,9,0,99,0,0)));
+        input.send(6).unwrap();
+        assert_eq!(output.recv().await, Some(6));
+        input.send(0).unwrap();
+        assert_eq!(output.recv().await, Some(0));
+        let (c, stopped) = handle.await.unwrap().unwrap();
+        assert_eq!((c.pc, stopped), (7, Stopped::Halted));
+    });
+}
+
+#[test]
+fn topology_chain_and_ring() {
+    let p = read_from_string("3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0").unwrap();
+    assert_eq!(Topology::chain(&p, &[4, 3, 2, 1, 0]).run(&[0]), Ok(Ok(vec!(43210))));
+
+    let p = read_from_string("3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5").unwrap();
+    let outputs = Topology::ring(&p, &[9, 8, 7, 6, 5]).run(&[0]).unwrap().unwrap();
+    assert_eq!(outputs.last(), Some(&139629729));
+}
+
+#[test]
+fn topology_fan_out_and_in() {
+    // Reads a value and outputs it plus a constant.
+    let add = |n| vec!(3,9,1001,9,n,9,4,9,99,0);
+    let mut t = Topology::new();
+    let source = t.node(&add(0), &[]);
+    let left = t.node(&add(10), &[]);
+    let right = t.node(&add(20), &[]);
+    // Reads two values and outputs their sum.
+    let sink = t.node(&[3,11,3,12,1,11,12,13,4,13,99,0,0,0], &[]);
+    t.edge(source, left).edge(source, right).edge(left, sink).edge(right, sink).exit(sink);
+    assert_eq!(t.run(&[1]), Ok(Ok(vec!(32))));
+    assert!(t.computer(left).output.is_empty());
+
+    let mut t = Topology::new();
+    t.node(&[3,0,99], &[]);
+    assert_eq!(t.run(&[]), Ok(Err(Stopped::Deadlock)));
+}
+
+#[test]
+fn router_with_nat() {
+    use crate::router::{Packet, Router, RouterEvent};
+
+    // Machine 0 sends (1, 7, 0). Every machine passes each packet it gets on
+    // to the next address with y increased by one.
+    let p = vec!(
+        3,100, 1001,100,1,101, 1005,100,15, 104,1, 104,7, 104,0,
+        3,102, 1008,102,-1,103, 1005,103,15, 3,104, 1001,104,1,104,
+        4,101, 4,102, 4,104, 1105,1,15);
+    let mut router = Router::new(&p, 3).with_nat(3, 0).unwrap();
+
+    let mut sent = Vec::new();
+    let event = router.run_until(100, |e| match e {
+        RouterEvent::Sent(packet) => { sent.push(*packet); false }
+        _ => true,
+    }).unwrap();
+    assert_eq!(sent, vec!(
+        Packet { dest: 1, x: 7, y: 0 },
+        Packet { dest: 2, x: 7, y: 1 },
+        Packet { dest: 3, x: 7, y: 2 }));
+    assert_eq!(event, Some(RouterEvent::NatSent(Packet { dest: 0, x: 7, y: 2 })));
+
+    let event = router.run_until(100, |e| matches!(e, RouterEvent::Sent(Packet { dest: 3, .. }))).unwrap();
+    assert_eq!(event, Some(RouterEvent::Sent(Packet { dest: 3, x: 7, y: 5 })));
+
+    // Without a NAT, packets to address 3 go nowhere.
+    let mut router = Router::new(&p, 3);
+    let event = router.run_until(100, |e| !matches!(e, RouterEvent::Sent(_))).unwrap();
+    assert_eq!(event, Some(RouterEvent::Dropped(Packet { dest: 3, x: 7, y: 2 })));
+    assert_eq!(router.run_until(10, |_| true), Ok(None));
+}
+
+#[test]
+fn router_nat_target_must_exist() {
+    let e = router::Router::new(&[99], 3).with_nat(255, 3).unwrap_err();
+    assert_eq!(e, router::BadNatTarget { target: 3, machines: 3 });
+    assert_eq!(e.to_string(), "NAT target 3 is out of range for 3 machines");
+}
+
+#[test]
+fn router_skips_halted_machines() {
+    use crate::router::{Packet, Router, RouterEvent};
+
+    // Machine 0 sends (1, 5, 6) and (255, 7, 8), then reads forever. Every
+    // other machine halts straight away.
+    let p = vec!(3,100, 1005,100,22, 104,1, 104,5, 104,6, 104,255, 104,7, 104,8, 3,101, 1105,1,17, 99);
+    let mut router = Router::new(&p, 2).with_nat(255, 0).unwrap();
+    assert_eq!(router.round(), Ok(vec!(
+        RouterEvent::Sent(Packet { dest: 1, x: 5, y: 6 }),
+        RouterEvent::Sent(Packet { dest: 255, x: 7, y: 8 }),
+        RouterEvent::Dropped(Packet { dest: 1, x: 5, y: 6 }))));
+    let event = router.run_until(10, |e| !matches!(e, RouterEvent::Sent(_))).unwrap();
+    assert_eq!(event, Some(RouterEvent::NatSent(Packet { dest: 0, x: 7, y: 8 })));
+
+    // The NAT can't wake a machine that's halted either.
+    let mut router = Router::new(&p, 2).with_nat(255, 1).unwrap();
+    let event = router.run_until(10, |e| matches!(e, RouterEvent::Dropped(Packet { dest: 1, x: 7, .. }))).unwrap();
+    assert_eq!(event, Some(RouterEvent::Dropped(Packet { dest: 1, x: 7, y: 8 })));
+    assert_eq!(router.run_until(10, |_| true), Ok(None));
+}
+
+// Prints "Hi", then echoes characters until it reads a '.', and finishes by
+// outputting 1000.
+const ECHO: [Word; 20] = [104,72,104,105,104,10,3,50,4,50,1008,50,46,51,1006,51,6,104,1000,99];
+
+#[test]
+fn ascii_io() {
+    let mut c = Computer::new(ECHO.to_vec());
+    c.run().unwrap();
+    assert_eq!(c.take_ascii_output().lines(), vec!("Hi"));
+
+    c.send_line("x.\r\n");
+    assert_eq!(c.input, vec!(120, 46, 10));
+    assert!(c.run().unwrap().done);
+    assert_eq!(c.input, vec!(10));
+    let output = c.take_ascii_output();
+    assert_eq!(output.text, "x.");
+    assert_eq!(output.values, vec!(1000));
+
+    let mut c = Computer::new(ECHO.to_vec());
+    c.send_ascii("ab");
+    assert!(c.run().unwrap().input_needed);
+    assert_eq!(c.take_ascii_output().text, "Hi\nab");
+}
+
+#[test]
+fn ascii_interact() {
+    let mut c = Computer::new(ECHO.to_vec());
+    let mut output = Vec::new();
+    assert!(c.interact(&b"ab\ncd.\n"[..], &mut output).unwrap().done);
+    assert_eq!(String::from_utf8(output).unwrap(), "Hi\nab\ncd.\n1000\n");
+
+    let mut c = Computer::new(ECHO.to_vec());
+    let mut output = Vec::new();
+    assert!(c.interact(&b"ab\n"[..], &mut output).unwrap().input_needed);
+    assert_eq!(String::from_utf8(output).unwrap(), "Hi\nab\n");
+
+    for p in [vec!(104,97,104,1000,99), vec!(104,97,104,10,104,1000,99)] {
+        let mut output = Vec::new();
+        Computer::new(p).interact(&b""[..], &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "a\n1000\n");
+    }
+}
+
+#[test]
+fn decode_cache_sees_self_modifying_code() {
+    // Runs the jump at 10 once, then overwrites it with a halt and goes back.
+    let p = vec!(1005,20,10, 1101,1,0,20, 1105,1,10, 1106,0,13, 1101,0,99,10, 1105,1,10, 0);
+    let mut c = Computer::new(p);
+    c.set_fuel(Some(100));
+    c.enable_decode_cache();
+    c.enable_history(None);
+    assert!(c.run().unwrap().done);
+    assert_eq!(c.pc, 10);
+    assert_eq!(c.instructions_executed(), 6);
+
+    // Undoing the store has to forget the halt too.
+    c.rewind(2);
+    assert_eq!(c.mem[10], 1106);
+    c.pc = 10;
+    c.step().unwrap();
+    assert_eq!(c.pc, 13);
+}
+
+#[test]
+fn decode_cache_runs_the_same_as_step() {
+    // Self-modifying code, the day 9 quine, reading input, and an overflow.
+    let programs = vec!(
+        vec!(1005,20,10, 1101,1,0,20, 1105,1,10, 1106,0,13, 1101,0,99,10, 1105,1,10, 0),
+        vec!(109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99),
+        vec!(3,9,4,9,1005,9,0,99,0,0),
+        vec!(1101,1,0,9,1002,9,2,9,1106,0,4,99));
+    for p in programs {
+        let run = |cached: bool| {
+            let mut c = Computer::new(p.clone());
+            if cached {
+                c.enable_decode_cache();
+            }
+            c.input.extend(&[3, 2]);
+            let result = c.run().map(|r| (r.done, r.input_needed));
+            (result, c.pc, c.instructions_executed(), c.take_output(), c.mem)
+        };
+        assert_eq!(run(true), run(false));
+    }
+}
+
+#[test]
+fn aot_compiles_only_reachable_code() {
+    // GOTO 5; data; EXIT
+    let source = aot::compile(&[1105,1,5,1234,-1,99]);
+    assert!(source.contains("            0 => {"));
+    assert!(source.contains("            5 => {"));
+    assert!(!source.contains("            3 => {"));
+    assert!(source.contains("const CODE: &[(usize, usize)] = &[(0, 3), (5, 6)];"));
+}
+
+#[test]
+fn aot_is_code_uses_ranges() {
+    let code = [(0, 3), (5, 6)];
+    assert!(aot::__rt::is_code(&code, 0));
+    assert!(aot::__rt::is_code(&code, 2));
+    assert!(!aot::__rt::is_code(&code, 3));
+    assert!(aot::__rt::is_code(&code, 5));
+    assert!(!aot::__rt::is_code(&code, 6));
+}
+
+#[test]
+fn aot_can_run_remembers_unchanged_code() {
+    let p = vec!(1105,1,5,1234,-1,99);
+    let code = [(0, 3), (5, 6)];
+    let mut c = Computer::new(p.clone());
+    assert!(aot::can_run(&mut c, &p, &code));
+    assert_eq!(c.compiled_code, Some((p.as_ptr() as usize, p.len())));
+
+    // A store outside the code keeps what was found.
+    assert_eq!(aot::__rt::store(&mut c, &code, 3, 7), Ok(false));
+    assert_eq!(c.compiled_code, Some((p.as_ptr() as usize, p.len())));
+    assert_eq!(aot::__rt::store(&mut c, &code, 1, 0), Ok(true));
+    assert_eq!(c.compiled_code, None);
+    assert!(!aot::can_run(&mut c, &p, &code));
+
+    c.write_mem(1, 1).unwrap();
+    assert!(aot::can_run(&mut c, &p, &code));
+    c.mem[5] = 0;
+    c.invalidate_decode_cache();
+    assert!(!aot::can_run(&mut c, &p, &code));
+}
+
+#[test]
+fn cfg_splits_blocks_at_jumps() {
+    // IN [12]; L2: OUT [12]; [12] = ADD [12],-1; IF [12] GOTO L2; EXIT; data
+    let p = vec!(3,12,4,12,1001,12,-1,12,1005,12,2,99,0,77);
+    let cfg = cfg::analyze(&p);
+
+    let starts: Vec<usize> = cfg.blocks.keys().copied().collect();
+    assert_eq!(starts, vec!(0,2,11));
+    assert_eq!(cfg.blocks[&0].successors, vec!(2));
+    assert_eq!(cfg.blocks[&2].insts.len(), 3);
+    assert_eq!(cfg.blocks[&2].end(), 11);
+    assert_eq!(cfg.blocks[&2].successors, vec!(2,11));
+    assert!(cfg.blocks[&11].halts());
+    assert!(cfg.code_stores.is_empty());
+    assert_eq!(cfg.indirect_jumps().count(), 0);
+    assert_eq!(cfg.block_containing(9).map(|b| b.start), Some(2));
+    assert_eq!(cfg.block_containing(12), None);
+}
+
+#[test]
+fn cfg_marks_indirect_jumps_and_code_stores() {
+    // [3] = ADD [9],[10]; IF [8] GOTO [11]; EXIT; data
+    let p = vec!(1,9,10,3,5,8,11,99,0,30,40,7);
+    let cfg = cfg::analyze(&p);
+
+    assert_eq!(cfg.indirect_jumps().collect::<Vec<_>>(), vec!(4));
+    assert!(cfg.blocks[&0].indirect);
+    assert_eq!(cfg.code_stores, vec!(cfg::CodeStore { pc: 0, address: 3 }));
+
+    let dot = cfg.to_dot();
+    assert!(dot.starts_with("digraph cfg {\n"));
+    assert!(dot.contains("    L0 [label=\"L0:\\l     0  ADD pos:9 pos:10 pos:3\\l     4  JT pos:8 pos:11\\l\", color=red];\n"));
+    assert!(dot.contains("    L0 -> L7;\n"));
+    assert!(dot.contains("    L0 -> indirect [style=dashed];\n"));
+    assert!(dot.contains("    L7 [label=\"L7:\\l     7  HALT\\l\", peripheries=2];\n"));
+    assert!(dot.ends_with("}\n"));
+}
+
+#[test]
+fn decompile_recognizes_calls_frames_and_loops() {
+    // main: ARB 50; [rb-1] = IN; call L18([rb-1]); OUT [rb+1]; EXIT
+    // L18: ARB 3; sum = 0; while n != 0 { sum += n; n -= 1 }; n = sum; ARB -3; return
+    let p = vec!(
+        109,50,203,-1,21101,15,0,0,21201,-1,0,1,1105,1,18,204,1,99,
+        109,3,21101,0,0,-1,1206,-2,38,22201,-1,-2,-1,21201,-2,-1,-2,1105,1,24,21201,-1,0,-2,109,-3,2106,0,0);
+    let mut c = Computer::new(p.clone());
+    c.send_input(4);
+    c.run().unwrap();
+    assert_eq!(c.take_output(), vec!(10));
+
+    assert_eq!(decompile::decompile(&p), "\
+fn main() {
+    local49 = input()
+    f18(local49)
+    output(rb[1])
+    halt
+}
+
+fn f18(arg1) {
+    local2 = 0
+    loop {
+        if (arg1 == 0) break
+        local2 = local2 + arg1
+        arg1 = arg1 - 1
+    }
+    arg1 = local2
+    return
+}
+");
+}
+
+#[test]
+fn decompile_structures_branches() {
+    // IN [20]; IF [20] < 5 { OUT 1 } ELSE { OUT 2 }; EXIT
+    let p = vec!(3,20,1007,20,5,21,1006,21,14,104,1,1105,1,16,104,2,99,0,0,0,0,0);
+    assert_eq!(decompile::decompile(&p), "\
+fn main() {
+    mem[20] = input()
+    if (mem[20] < 5) {
+        output(1)
+    } else {
+        output(2)
+    }
+    halt
+}
+");
+
+    // IN [12]; L2: OUT [12]; [12] = ADD [12],-1; IF [12] GOTO L2; EXIT
+    let p = vec!(3,12,4,12,1001,12,-1,12,1005,12,2,99,0,77);
+    assert_eq!(decompile::decompile(&p), "\
+fn main() {
+    mem[12] = input()
+    do {
+        output(mem[12])
+        mem[12] = mem[12] - 1
+    } while (mem[12] != 0)
+    halt
+}
+");
+}
+
+#[test]
+fn decompile_leaves_unstructured_jumps_as_gotos() {
+    // IN [13]; IF [13] GOTO L9; OUT 1; L7: OUT 2; L9: OUT 3; GOTO L7. The
+    // loop from L7 can be entered at L9, so it can't be structured.
+    let p = vec!(3,13,1005,13,9,104,1,104,2,104,3,1105,1,7,0);
+    assert_eq!(decompile::decompile(&p), "\
+fn main() {
+    mem[13] = input()
+    if (mem[13] != 0) goto L9
+    output(1)
+L7:
+    output(2)
+L9:
+    output(3)
+    goto L7
+}
+");
+}
+
+#[test]
+fn optimize_compacts_relocatable_programs() {
+    // IN [40]; [41] = 2 + 3; [42] = 7; [42] = [40] + 1; IF [40] GOTO 17;
+    // L17: IF ![40] GOTO 25; OUT 99; GOTO 28; L25: GOTO 28;
+    // L28: OUT [42]; OUT [41]; EXIT; data
+    let mut p = vec!(3,40,1101,2,3,41,1101,7,0,42,1001,40,1,42,1005,40,17,1006,40,25,104,99,1105,1,28,1105,1,28,4,42,4,41,99);
+    p.resize(43, 0);
+    let optimized = optimize::optimize(&p);
+
+    // The dead store and the jump to the next instruction are gone, and
+    // everything after them has moved up.
+    assert_eq!(optimized.len(), p.len() - 7);
+    assert_eq!(optimized[..18].to_vec(), vec!(3,33,1101,2,3,34,1001,33,1,35,1006,33,21,104,99,1105,1,21));
+    let inputs = vec!(vec!(5), vec!(0), vec!(-3), vec!());
+    assert_eq!(optimize::verify(&p, &optimized, &inputs), Ok(()));
+
+    let mut c = Computer::new(optimized.clone());
+    c.send_input(5);
+    c.run().unwrap();
+    assert_eq!(c.take_output(), vec!(99, 6, 5));
+    assert_eq!(c.instructions_executed(), 8);
+
+    let mut c = Computer::new(optimized);
+    c.send_input(0);
+    c.run().unwrap();
+    assert_eq!(c.take_output(), vec!(1, 5));
+    assert_eq!(c.instructions_executed(), 6);
+}
+
+#[test]
+fn optimize_keeps_addresses_when_not_relocatable() {
+    // ARB 50; IN [40]; [41] = 7; [41] = [40] + 1; GOTO 15; L15: OUT [41]; EXIT
+    let p = vec!(109,50,3,40,1101,7,0,41,1001,40,1,41,1105,1,15,4,41,99);
+    let optimized = optimize::optimize(&p);
+
+    // The dead store becomes a jump over it, and the jump to the next
+    // instruction is left, since it's no slower than a jump over itself.
+    assert_eq!(optimized.len(), p.len());
+    assert_eq!(optimized[4..7].to_vec(), vec!(1105,1,8));
+    assert_eq!(optimized[7..].to_vec(), p[7..].to_vec());
+    assert_eq!(optimize::verify(&p, &optimized, &[vec!(1), vec!(41)]), Ok(()));
+}
+
+#[test]
+fn optimize_leaves_code_read_as_data_alone() {
+    // [9] = 2 + 3; OUT [2]; OUT [9]; EXIT
+    let p = vec!(1101,2,3,9,4,2,4,9,99,0);
+    assert_eq!(optimize::optimize(&p), p);
+}
+
+#[test]
+fn optimize_folds_constants() {
+    // [40] = 2 * 3; OUT [40]; [40] = [40] + 1; OUT [40]; EXIT
+    let mut p = vec!(1102,2,3,40,4,40,1001,40,1,40,4,40,99);
+    p.resize(41, 0);
+    let optimized = optimize::optimize(&p);
+
+    // Both reads use the constant, which leaves the first store dead.
+    assert_eq!(optimized.len(), p.len() - 4);
+    assert_eq!(optimized[..9].to_vec(), vec!(104,6,1101,6,1,36,104,7,99));
+    assert_eq!(optimize::verify(&p, &optimized, &[vec!()]), Ok(()));
+}
+
+#[test]
+fn optimize_keeps_targets_read_as_data() {
+    // GOTO 5; EXIT; EXIT; L5: GOTO 8; L8: OUT [2]; EXIT. The first jump's
+    // target is what gets printed, so it can't be moved past the second.
+    let p = vec!(1105,1,5,99,99,1105,1,8,4,2,99);
+    let optimized = optimize::optimize(&p);
+    assert_eq!(optimized[..3].to_vec(), vec!(1105,1,5));
+    assert_eq!(optimize::verify(&p, &optimized, &[vec!()]), Ok(()));
+}
+
+#[test]
+fn optimize_keeps_stores_into_code() {
+    // [5] = 7; OUT 0; [5] = 9; EXIT. The first store changes what OUT prints.
+    let p = vec!(1101,7,0,5,104,0,1101,9,0,5,99);
+    let optimized = optimize::optimize(&p);
+    assert_eq!(optimize::verify(&p, &optimized, &[vec!()]), Ok(()));
+
+    let mut c = Computer::new(optimized);
+    c.run().unwrap();
+    assert_eq!(c.take_output(), vec!(7));
+}
+
+#[test]
+fn verify_reports_differences() {
+    // OUT [IN]; EXIT vs. OUT 1; EXIT
+    let p = vec!(3,5,4,5,99,0);
+    let other = vec!(104,1,99);
+    let mismatch = optimize::verify(&p, &other, &[vec!(1), vec!(2)]).unwrap_err();
+    assert_eq!(mismatch.input, 1);
+    assert_eq!(mismatch.original, (vec!(2), optimize::Outcome::Halted));
+    assert_eq!(mismatch.optimized, (vec!(1), optimize::Outcome::Halted));
+
+    // Errors only have to be the same kind.
+    let bad = vec!(4,-1,99);
+    let moved = vec!(1105,1,3,4,-1,99);
+    assert_eq!(optimize::verify(&bad, &moved, &[vec!()]), Ok(()));
+}
@@ -0,0 +1,238 @@
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::{Computer, Error, Stopped, Word};
+
+// Machines waiting for input wake up this often to see whether the network
+// has deadlocked.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// The network is deadlocked when every machine that's still running is
+// waiting for input, nothing has been sent that hasn't been received, and
+// nobody outside the network can send anything.
+#[derive(Debug, Default)]
+struct State {
+    alive: usize,
+    blocked: usize,
+    in_flight: usize,
+    external_inputs: usize,
+    deadlocked: bool,
+}
+
+impl State {
+    fn check(&mut self) {
+        if self.alive > 0 && self.blocked == self.alive && self.in_flight == 0 && self.external_inputs == 0 {
+            self.deadlocked = true;
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    state: Mutex<State>,
+}
+
+impl Shared {
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Sending while holding the lock means a receiver can never see a value
+    // before it's been counted.
+    fn send(&self, tx: &Sender<Word>, value: Word) -> bool {
+        let mut state = self.lock();
+        let sent = tx.send(value).is_ok();
+        if sent {
+            state.in_flight += 1;
+        }
+        sent
+    }
+
+    fn wait_for_input(&self, rx: &Receiver<Word>) -> Result<Word, Stopped> {
+        {
+            let mut state = self.lock();
+            state.blocked += 1;
+            state.check();
+        }
+        loop {
+            let received = rx.recv_timeout(POLL_INTERVAL);
+            let mut state = self.lock();
+            match received {
+                Ok(value) => {
+                    state.blocked -= 1;
+                    state.in_flight -= 1;
+                    return Ok(value);
+                }
+                Err(RecvTimeoutError::Timeout) if !state.deadlocked => {}
+                // Machines that give up on a deadlock drop their senders, so
+                // the rest may see their inputs disconnect first.
+                Err(_) if state.deadlocked => {
+                    state.blocked -= 1;
+                    return Err(Stopped::Deadlock);
+                }
+                Err(_) => {
+                    state.blocked -= 1;
+                    return Err(Stopped::Disconnected);
+                }
+            }
+        }
+    }
+
+    // Anything left in a finished machine's input will never be received.
+    fn finish(&self, rx: Receiver<Word>) {
+        let mut state = self.lock();
+        while rx.try_recv().is_ok() {
+            state.in_flight -= 1;
+        }
+        drop(rx);
+        state.alive -= 1;
+        state.check();
+    }
+}
+
+// A way for the outside world to send input to a machine in a network. While
+// any of these exist, machines waiting for input aren't considered deadlocked.
+pub struct Input {
+    tx: Sender<Word>,
+    shared: Arc<Shared>,
+}
+
+impl Input {
+    // Returns false if the machine has already finished.
+    pub fn send(&self, value: Word) -> bool {
+        self.shared.send(&self.tx, value)
+    }
+}
+
+impl Clone for Input {
+    fn clone(&self) -> Self {
+        self.shared.lock().external_inputs += 1;
+        Input { tx: self.tx.clone(), shared: self.shared.clone() }
+    }
+}
+
+impl Drop for Input {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.external_inputs -= 1;
+        state.check();
+    }
+}
+
+struct Node {
+    computer: Computer,
+    tx: Sender<Word>,
+    rx: Receiver<Word>,
+    targets: Vec<usize>,
+    tap: Option<Sender<Word>>,
+}
+
+// Machines whose outputs feed each other's inputs, each on its own thread.
+// A machine with nowhere to send its output keeps it in its output queue.
+#[derive(Default)]
+pub struct Network {
+    nodes: Vec<Node>,
+    shared: Arc<Shared>,
+}
+
+impl Network {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Returns an id for connect, input and output.
+    pub fn add(&mut self, computer: Computer) -> usize {
+        let (tx, rx) = mpsc::channel();
+        self.nodes.push(Node { computer, tx, rx, targets: Vec::new(), tap: None });
+        self.nodes.len() - 1
+    }
+
+    // Sends every output of from to the input of to. A machine can feed any
+    // number of others.
+    pub fn connect(&mut self, from: usize, to: usize) -> &mut Self {
+        self.nodes[from].targets.push(to);
+        self
+    }
+
+    pub fn input(&mut self, node: usize) -> Input {
+        self.shared.lock().external_inputs += 1;
+        Input { tx: self.nodes[node].tx.clone(), shared: self.shared.clone() }
+    }
+
+    // Receives a copy of everything the machine outputs.
+    pub fn output(&mut self, node: usize) -> Receiver<Word> {
+        let (tx, rx) = mpsc::channel();
+        self.nodes[node].tap = Some(tx);
+        rx
+    }
+
+    pub fn start(self) -> Running {
+        let senders: Vec<Sender<Word>> = self.nodes.iter().map(|n| n.tx.clone()).collect();
+        self.shared.lock().alive = self.nodes.len();
+
+        let mut handles = Vec::new();
+        for node in self.nodes {
+            let targets: Vec<Sender<Word>> = node.targets.iter().map(|&t| senders[t].clone()).collect();
+            let shared = self.shared.clone();
+            let Node { mut computer, rx, tap, .. } = node;
+            handles.push(thread::spawn(move || {
+                let result = drive(&mut computer, &rx, &targets, tap.as_ref(), &shared);
+                shared.finish(rx);
+                result.map(|stopped| (computer, stopped))
+            }));
+        }
+        Running { handles }
+    }
+}
+
+fn drive(c: &mut Computer, rx: &Receiver<Word>, targets: &[Sender<Word>], tap: Option<&Sender<Word>>, shared: &Shared) -> Result<Stopped, Error> {
+    loop {
+        let result = c.step()?;
+        if !targets.is_empty() || tap.is_some() {
+            for value in c.output.drain(..) {
+                for target in targets {
+                    shared.send(target, value);
+                }
+                if let Some(tap) = tap {
+                    let _ = tap.send(value);
+                }
+            }
+        }
+        if result.done {
+            return Ok(Stopped::Halted);
+        }
+        if result.input_needed {
+            match shared.wait_for_input(rx) {
+                Ok(value) => c.send_input(value),
+                Err(stopped) => return Ok(stopped),
+            }
+        }
+    }
+}
+
+pub struct Running {
+    handles: Vec<JoinHandle<Result<(Computer, Stopped), Error>>>,
+}
+
+impl Running {
+    // Waits for every machine to finish and returns them in the order they
+    // were added, each with why it stopped: it halted, or it was waiting for
+    // input when the network deadlocked or its senders went away. If any
+    // failed, returns the first error.
+    pub fn join(self) -> Result<Vec<(Computer, Stopped)>, Error> {
+        self.handles.into_iter()
+            .map(|h| h.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+            .collect()
+    }
+}
+
+// Runs a single machine on its own thread.
+pub fn spawn(computer: Computer) -> (Input, Receiver<Word>, Running) {
+    let mut network = Network::new();
+    let node = network.add(computer);
+    let input = network.input(node);
+    let output = network.output(node);
+    (input, output, network.start())
+}
@@ -0,0 +1,120 @@
+use crate::{Computer, Error, Stopped, Word};
+
+// Machines wired output-to-input, run round-robin on the current thread.
+// Input goes to the entry machine, and the result is whatever the exit
+// machine outputs before it halts.
+#[derive(Clone, Debug, Default)]
+pub struct Topology {
+    nodes: Vec<Computer>,
+    edges: Vec<(usize, usize)>,
+    entry: usize,
+    exit: usize,
+}
+
+impl Topology {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Machines running p in a line, each given one of the initial inputs
+    // (like phase settings) before anything else.
+    pub fn chain(p: &[Word], initial_inputs: &[Word]) -> Self {
+        let mut t = Topology::new();
+        for &input in initial_inputs {
+            let node = t.node(p, &[input]);
+            if node > 0 {
+                t.edge(node - 1, node);
+            }
+        }
+        let last = t.nodes.len().saturating_sub(1);
+        t.exit(last);
+        t
+    }
+
+    // A chain whose last machine also feeds the first.
+    pub fn ring(p: &[Word], initial_inputs: &[Word]) -> Self {
+        let mut t = Self::chain(p, initial_inputs);
+        if !t.nodes.is_empty() {
+            t.edge(t.exit, 0);
+        }
+        t
+    }
+
+    // Returns an id for edge, entry and exit. The first node added is the
+    // entry and exit until told otherwise.
+    pub fn node(&mut self, p: &[Word], initial_inputs: &[Word]) -> usize {
+        let mut c = Computer::new(p.to_vec());
+        c.input.extend(initial_inputs);
+        self.add(c)
+    }
+
+    pub fn add(&mut self, computer: Computer) -> usize {
+        self.nodes.push(computer);
+        self.nodes.len() - 1
+    }
+
+    // Everything from outputs is copied to the input of every machine it
+    // has an edge to, so a machine can fan out to several others or take
+    // input from several.
+    pub fn edge(&mut self, from: usize, to: usize) -> &mut Self {
+        self.edges.push((from, to));
+        self
+    }
+
+    pub fn entry(&mut self, node: usize) -> &mut Self {
+        self.entry = node;
+        self
+    }
+
+    pub fn exit(&mut self, node: usize) -> &mut Self {
+        self.exit = node;
+        self
+    }
+
+    pub fn computer(&self, node: usize) -> &Computer {
+        &self.nodes[node]
+    }
+
+    pub fn computers_mut(&mut self) -> impl Iterator<Item = &mut Computer> {
+        self.nodes.iter_mut()
+    }
+
+    // Sends input to the entry machine, then runs every machine in turn until
+    // the exit machine halts, and returns everything the exit machine output.
+    // Stops with Stopped::Deadlock if no machine can make progress first.
+    pub fn run(&mut self, input: &[Word]) -> Result<Result<Vec<Word>, Stopped>, Error> {
+        if self.nodes.is_empty() {
+            return Ok(Ok(Vec::new()));
+        }
+        self.nodes[self.entry].input.extend(input);
+
+        let mut result = Vec::new();
+        loop {
+            let mut progress = false;
+            for i in 0..self.nodes.len() {
+                let before = self.nodes[i].instructions_executed();
+                let step = self.nodes[i].run()?;
+                progress |= self.nodes[i].instructions_executed() != before;
+
+                let outputs = self.nodes[i].take_output();
+                let targets: Vec<usize> = self.edges.iter().filter(|e| e.0 == i).map(|e| e.1).collect();
+                for &to in &targets {
+                    self.nodes[to].input.extend(&outputs);
+                }
+                if i == self.exit {
+                    result.extend(&outputs);
+                    if step.done {
+                        return Ok(Ok(result));
+                    }
+                } else if targets.is_empty() {
+                    // Nothing listens to this machine, so leave its output
+                    // where it was.
+                    self.nodes[i].output.extend(outputs);
+                }
+            }
+            if !progress {
+                return Ok(Err(Stopped::Deadlock));
+            }
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use crate::{Inst, Word};
+
+// One executed instruction. operands holds the values loaded for the
+// instruction's input parameters, in order, and store is the (address, value)
+// it wrote, if any.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TraceEvent<V = Word> {
+    pub pc: usize,
+    pub inst: Inst<V>,
+    pub operands: [Option<V>; 2],
+    pub store: Option<(usize, V)>,
+    pub relative_base: V,
+}
+
+impl<V: fmt::Display> fmt::Display for TraceEvent<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let p1 = or_zero(self.operands[0].as_ref());
+        let p2 = or_zero(self.operands[1].as_ref());
+        let pc = self.pc;
+        match &self.inst {
+            Inst::Add(src1, src2, dst) =>
+                write!(f, "{}: {} = ADD {} ({}) {} ({})", pc, dst, src1, p1, src2, p2),
+            Inst::Mult(src1, src2, dst) =>
+                write!(f, "{}: {} = MULT {} ({}) {} ({})", pc, dst, src1, p1, src2, p2),
+            Inst::Input(dst) =>
+                write!(f, "{}: {} = INPUT {}", pc, dst, or_zero(self.store.as_ref().map(|(_, v)| v))),
+            Inst::Output(src) =>
+                write!(f, "{}: OUTPUT {} ({})", pc, src, p1),
+            Inst::JumpIfTrue(cond, target) =>
+                write!(f, "{}: IF {} ({}) GOTO {} ({})", pc, cond, p1, target, p2),
+            Inst::JumpIfFalse(cond, target) =>
+                write!(f, "{}: IF NOT {} ({}) GOTO {} ({})", pc, cond, p1, target, p2),
+            Inst::LessThan(src1, src2, dst) =>
+                write!(f, "{}: {} = {} ({}) < {} ({})", pc, dst, src1, p1, src2, p2),
+            Inst::Equal(src1, src2, dst) =>
+                write!(f, "{}: {} = {} ({}) == {} ({})", pc, dst, src1, p1, src2, p2),
+            Inst::AdjustBase(src) =>
+                write!(f, "{}: ADJUST BASE BY {} ({}), NOW {}", pc, src, p1, self.relative_base),
+            Inst::Exit =>
+                write!(f, "{}: EXIT", pc),
+        }
+    }
+}
+
+fn or_zero<V: fmt::Display>(value: Option<&V>) -> String {
+    value.map_or("0".to_string(), |v| v.to_string())
+}
+
+impl<V: fmt::Display> TraceEvent<V> {
+    pub fn to_json(&self) -> String {
+        let operands: Vec<String> = self.operands.iter().flatten().map(|v| v.to_string()).collect();
+        let store = match &self.store {
+            Some((address, value)) => format!("{{\"address\":{},\"value\":{}}}", address, value),
+            None => "null".to_string(),
+        };
+        format!(
+            "{{\"pc\":{},\"inst\":\"{}\",\"operands\":[{}],\"store\":{},\"relative_base\":{}}}",
+            self.pc, self.inst, operands.join(","), store, self.relative_base)
+    }
+}
+
+// V is the Computer's type of word, see Int.
+pub trait Tracer<V = Word> {
+    fn trace(&mut self, event: &TraceEvent<V>);
+}
+
+// Writes each event as a line of text, in the same format that tracing used
+// to print to stdout.
+pub struct WriteTracer<W: Write> {
+    pub writer: W,
+}
+
+impl<W: Write> WriteTracer<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl<V: fmt::Display, W: Write> Tracer<V> for WriteTracer<W> {
+    fn trace(&mut self, event: &TraceEvent<V>) {
+        // Tracing shouldn't be able to stop the program, so write errors are dropped.
+        let _ = writeln!(self.writer, "{}", event);
+    }
+}
+
+// Writes each event as a JSON object, one per line.
+pub struct JsonTracer<W: Write> {
+    pub writer: W,
+}
+
+impl<W: Write> JsonTracer<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl<V: fmt::Display, W: Write> Tracer<V> for JsonTracer<W> {
+    fn trace(&mut self, event: &TraceEvent<V>) {
+        let _ = writeln!(self.writer, "{}", event.to_json());
+    }
+}
+
+// Keeps the last few events, for looking at what led up to an error.
+#[derive(Clone, Debug)]
+pub struct RingTracer<V = Word> {
+    capacity: usize,
+    events: VecDeque<TraceEvent<V>>,
+}
+
+impl<V: Clone> RingTracer<V> {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, events: VecDeque::with_capacity(capacity) }
+    }
+
+    pub fn events(&self) -> Vec<TraceEvent<V>> {
+        self.events.iter().cloned().collect()
+    }
+}
+
+impl<V: Clone> Tracer<V> for RingTracer<V> {
+    fn trace(&mut self, event: &TraceEvent<V>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(event.clone());
+    }
+}
+
+pub type SharedTracer<V = Word> = Arc<Mutex<dyn Tracer<V> + Send>>;
+
+pub(crate) struct TracerHandle<V>(pub(crate) SharedTracer<V>);
+
+// Not derived, since that would need V: Clone.
+impl<V> Clone for TracerHandle<V> {
+    fn clone(&self) -> Self {
+        TracerHandle(self.0.clone())
+    }
+}
+
+impl<V> fmt::Debug for TracerHandle<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Tracer")
+    }
+}
+
+impl<V> TracerHandle<V> {
+    pub(crate) fn trace(&self, event: &TraceEvent<V>) {
+        if let Ok(mut tracer) = self.0.lock() {
+            tracer.trace(event);
+        }
+    }
+}
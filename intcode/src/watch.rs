@@ -0,0 +1,76 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::Word;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl WatchKind {
+    fn matches(self, access: Access) -> bool {
+        match self {
+            WatchKind::Read => access == Access::Read,
+            WatchKind::Write => access == Access::Write,
+            WatchKind::ReadWrite => true,
+        }
+    }
+}
+
+// For reads, old_value and new_value are both the value that was read.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct WatchEvent<W = Word> {
+    pub pc: usize,
+    pub address: usize,
+    pub access: Access,
+    pub old_value: W,
+    pub new_value: W,
+}
+
+pub type WatchCallback<W = Word> = Arc<dyn Fn(&WatchEvent<W>) + Send + Sync>;
+
+pub enum WatchAction<W = Word> {
+    // Finish the current instruction, then report the event from step().
+    Pause,
+    Callback(WatchCallback<W>),
+}
+
+impl<W> Clone for WatchAction<W> {
+    fn clone(&self) -> Self {
+        match self {
+            WatchAction::Pause => WatchAction::Pause,
+            WatchAction::Callback(f) => WatchAction::Callback(f.clone()),
+        }
+    }
+}
+
+impl<W> fmt::Debug for WatchAction<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatchAction::Pause => write!(f, "Pause"),
+            WatchAction::Callback(_) => write!(f, "Callback"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Watchpoint<W = Word> {
+    pub address: usize,
+    pub kind: WatchKind,
+    pub action: WatchAction<W>,
+}
+
+impl<W> Watchpoint<W> {
+    pub fn matches(&self, address: usize, access: Access) -> bool {
+        self.address == address && self.kind.matches(access)
+    }
+}
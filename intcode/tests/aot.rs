@@ -0,0 +1,74 @@
+use intcode::{Computer, Word};
+
+// Generated with the aot binary. If compile's output changes, regenerate them:
+//   cargo run --bin aot program.txt tests/aot/<name>.rs
+mod quine {
+    include!("aot/quine.rs");
+}
+
+mod modify {
+    include!("aot/modify.rs");
+}
+
+mod compare {
+    include!("aot/compare.rs");
+}
+
+#[test]
+fn generated_code_is_up_to_date() {
+    assert_eq!(intcode::aot::compile(&quine::PROGRAM), include_str!("aot/quine.rs"));
+    assert_eq!(intcode::aot::compile(&modify::PROGRAM), include_str!("aot/modify.rs"));
+    assert_eq!(intcode::aot::compile(&compare::PROGRAM), include_str!("aot/compare.rs"));
+}
+
+#[test]
+fn compiled_matches_interpreter() {
+    let mut compiled = quine::new();
+    let result = quine::run(&mut compiled).unwrap();
+    assert!(result.done);
+
+    let mut interpreted = Computer::new(quine::PROGRAM.to_vec());
+    interpreted.run().unwrap();
+    assert_eq!(compiled.take_output(), quine::PROGRAM.to_vec());
+    assert_eq!(compiled.mem, interpreted.mem);
+    assert_eq!(compiled.pc, interpreted.pc);
+    assert_eq!(compiled.relative_base, interpreted.relative_base);
+    assert_eq!(compiled.instructions_executed(), interpreted.instructions_executed());
+}
+
+#[test]
+fn compiled_waits_for_input() {
+    for (input, expected) in [(7, 999), (8, 1000), (9, 1001)] {
+        let mut c = compare::new();
+        let result = compare::run(&mut c).unwrap();
+        assert!(result.input_needed);
+        assert_eq!(c.pc, 0);
+
+        c.send_input(input);
+        let result = compare::run(&mut c).unwrap();
+        assert!(result.done);
+        assert_eq!(c.take_output(), vec!(expected as Word));
+    }
+}
+
+#[test]
+fn self_modification_falls_back_to_interpreter() {
+    let mut c = modify::new();
+    assert!(modify::run(&mut c).unwrap().done);
+    assert_eq!(c.mem[0], 3500);
+
+    let mut interpreted = Computer::new(modify::PROGRAM.to_vec());
+    interpreted.run().unwrap();
+    assert_eq!(c.mem, interpreted.mem);
+    assert_eq!(c.instructions_executed(), interpreted.instructions_executed());
+
+    // Now that the code has changed, the compiled version can't be trusted.
+    assert!(!intcode::aot::can_run(&mut c, &modify::PROGRAM, &[(0, 9)]));
+}
+
+#[test]
+fn instrumented_computers_are_interpreted() {
+    let mut c = quine::new();
+    c.set_fuel(Some(10));
+    assert!(matches!(quine::run(&mut c), Err(intcode::Error::OutOfFuel { .. })));
+}
@@ -0,0 +1,153 @@
+// Generated by intcode::aot::compile. Don't edit.
+
+pub static PROGRAM: [intcode::Word; 47] = [
+    3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31,
+    1106, 0, 36, 98, 0, 0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104,
+    999, 1105, 1, 46, 1101, 1000, 1, 20, 4, 20, 1105, 1, 46, 98, 99,
+];
+
+const CODE: &[(usize, usize)] = &[(0, 19), (22, 45), (46, 47)];
+
+pub fn new() -> intcode::Computer {
+    intcode::Computer::new(PROGRAM.to_vec())
+}
+
+pub fn run(c: &mut intcode::Computer) -> Result<intcode::StepResult, intcode::Error> {
+    if !intcode::aot::can_run(c, &PROGRAM, CODE) {
+        return c.run();
+    }
+    let mut steps = 0;
+    let result = exec(c, &mut steps);
+    intcode::aot::__rt::count_steps(c, steps);
+    result
+}
+
+#[allow(unreachable_code, unused_imports)]
+fn exec(c: &mut intcode::Computer, steps: &mut u64) -> Result<intcode::StepResult, intcode::Error> {
+    use intcode::aot::__rt;
+    loop {
+        match c.pc {
+            // IN pos:21
+            0 => {
+                let value = match c.input.pop_front() {
+                    Some(value) => value,
+                    None => return Ok(__rt::input_needed(c)),
+                };
+                let into_code = __rt::store(c, CODE, 21, value)?;
+                c.pc = 2;
+                if into_code {
+                    *steps += 1;
+                    return c.run();
+                }
+            }
+            // EQ pos:21 imm:8 pos:20
+            2 => {
+                let lhs: intcode::Word = c.read_mem(21);
+                let rhs: intcode::Word = 8;
+                let value = (lhs == rhs) as intcode::Word;
+                let into_code = __rt::store(c, CODE, 20, value)?;
+                c.pc = 6;
+                if into_code {
+                    *steps += 1;
+                    return c.run();
+                }
+            }
+            // JT pos:20 imm:22
+            6 => {
+                let cond: intcode::Word = c.read_mem(20);
+                let target = 22;
+                c.pc = if cond != 0 { target } else { 9 };
+            }
+            // LT imm:8 pos:21 pos:20
+            9 => {
+                let lhs: intcode::Word = 8;
+                let rhs: intcode::Word = c.read_mem(21);
+                let value = (lhs < rhs) as intcode::Word;
+                let into_code = __rt::store(c, CODE, 20, value)?;
+                c.pc = 13;
+                if into_code {
+                    *steps += 1;
+                    return c.run();
+                }
+            }
+            // JF pos:20 imm:31
+            13 => {
+                let cond: intcode::Word = c.read_mem(20);
+                let target = 31;
+                c.pc = if cond == 0 { target } else { 16 };
+            }
+            // JF imm:0 imm:36
+            16 => {
+                let cond: intcode::Word = 0;
+                let target = 36;
+                c.pc = if cond == 0 { target } else { 19 };
+            }
+            // MULT pos:21 imm:125 pos:20
+            22 => {
+                let lhs: intcode::Word = c.read_mem(21);
+                let rhs: intcode::Word = 125;
+                let value = __rt::mul(c, lhs, rhs)?;
+                let into_code = __rt::store(c, CODE, 20, value)?;
+                c.pc = 26;
+                if into_code {
+                    *steps += 1;
+                    return c.run();
+                }
+            }
+            // OUT pos:20
+            26 => {
+                let value: intcode::Word = c.read_mem(20);
+                c.output.push_back(value);
+                c.pc = 28;
+            }
+            // JT imm:1 imm:46
+            28 => {
+                let cond: intcode::Word = 1;
+                let target = 46;
+                c.pc = if cond != 0 { target } else { 31 };
+            }
+            // OUT imm:999
+            31 => {
+                let value: intcode::Word = 999;
+                c.output.push_back(value);
+                c.pc = 33;
+            }
+            // JT imm:1 imm:46
+            33 => {
+                let cond: intcode::Word = 1;
+                let target = 46;
+                c.pc = if cond != 0 { target } else { 36 };
+            }
+            // ADD imm:1000 imm:1 pos:20
+            36 => {
+                let lhs: intcode::Word = 1000;
+                let rhs: intcode::Word = 1;
+                let value = __rt::add(c, lhs, rhs)?;
+                let into_code = __rt::store(c, CODE, 20, value)?;
+                c.pc = 40;
+                if into_code {
+                    *steps += 1;
+                    return c.run();
+                }
+            }
+            // OUT pos:20
+            40 => {
+                let value: intcode::Word = c.read_mem(20);
+                c.output.push_back(value);
+                c.pc = 42;
+            }
+            // JT imm:1 imm:46
+            42 => {
+                let cond: intcode::Word = 1;
+                let target = 46;
+                c.pc = if cond != 0 { target } else { 45 };
+            }
+            // HALT
+            46 => {
+                return Ok(__rt::halted(c));
+            }
+            _ => return c.run(),
+        }
+        *steps += 1;
+    }
+}
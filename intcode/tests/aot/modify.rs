@@ -0,0 +1,60 @@
+// Generated by intcode::aot::compile. Don't edit.
+
+pub static PROGRAM: [intcode::Word; 12] = [
+    1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50,
+];
+
+const CODE: &[(usize, usize)] = &[(0, 9)];
+
+pub fn new() -> intcode::Computer {
+    intcode::Computer::new(PROGRAM.to_vec())
+}
+
+pub fn run(c: &mut intcode::Computer) -> Result<intcode::StepResult, intcode::Error> {
+    if !intcode::aot::can_run(c, &PROGRAM, CODE) {
+        return c.run();
+    }
+    let mut steps = 0;
+    let result = exec(c, &mut steps);
+    intcode::aot::__rt::count_steps(c, steps);
+    result
+}
+
+#[allow(unreachable_code, unused_imports)]
+fn exec(c: &mut intcode::Computer, steps: &mut u64) -> Result<intcode::StepResult, intcode::Error> {
+    use intcode::aot::__rt;
+    loop {
+        match c.pc {
+            // ADD pos:9 pos:10 pos:3
+            0 => {
+                let lhs: intcode::Word = c.read_mem(9);
+                let rhs: intcode::Word = c.read_mem(10);
+                let value = __rt::add(c, lhs, rhs)?;
+                let into_code = __rt::store(c, CODE, 3, value)?;
+                c.pc = 4;
+                if into_code {
+                    *steps += 1;
+                    return c.run();
+                }
+            }
+            // MULT pos:3 pos:11 pos:0
+            4 => {
+                let lhs: intcode::Word = c.read_mem(3);
+                let rhs: intcode::Word = c.read_mem(11);
+                let value = __rt::mul(c, lhs, rhs)?;
+                let into_code = __rt::store(c, CODE, 0, value)?;
+                c.pc = 8;
+                if into_code {
+                    *steps += 1;
+                    return c.run();
+                }
+            }
+            // HALT
+            8 => {
+                return Ok(__rt::halted(c));
+            }
+            _ => return c.run(),
+        }
+        *steps += 1;
+    }
+}
@@ -0,0 +1,78 @@
+// Generated by intcode::aot::compile. Don't edit.
+
+pub static PROGRAM: [intcode::Word; 16] = [
+    109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+];
+
+const CODE: &[(usize, usize)] = &[(0, 16)];
+
+pub fn new() -> intcode::Computer {
+    intcode::Computer::new(PROGRAM.to_vec())
+}
+
+pub fn run(c: &mut intcode::Computer) -> Result<intcode::StepResult, intcode::Error> {
+    if !intcode::aot::can_run(c, &PROGRAM, CODE) {
+        return c.run();
+    }
+    let mut steps = 0;
+    let result = exec(c, &mut steps);
+    intcode::aot::__rt::count_steps(c, steps);
+    result
+}
+
+#[allow(unreachable_code, unused_imports)]
+fn exec(c: &mut intcode::Computer, steps: &mut u64) -> Result<intcode::StepResult, intcode::Error> {
+    use intcode::aot::__rt;
+    loop {
+        match c.pc {
+            // ARB imm:1
+            0 => {
+                let offset: intcode::Word = 1;
+                c.relative_base = __rt::relative(c, offset)?;
+                c.pc = 2;
+            }
+            // OUT rel:-1
+            2 => {
+                let value: intcode::Word = __rt::load(c, __rt::relative(c, -1)?)?;
+                c.output.push_back(value);
+                c.pc = 4;
+            }
+            // ADD pos:100 imm:1 pos:100
+            4 => {
+                let lhs: intcode::Word = c.read_mem(100);
+                let rhs: intcode::Word = 1;
+                let value = __rt::add(c, lhs, rhs)?;
+                let into_code = __rt::store(c, CODE, 100, value)?;
+                c.pc = 8;
+                if into_code {
+                    *steps += 1;
+                    return c.run();
+                }
+            }
+            // EQ pos:100 imm:16 pos:101
+            8 => {
+                let lhs: intcode::Word = c.read_mem(100);
+                let rhs: intcode::Word = 16;
+                let value = (lhs == rhs) as intcode::Word;
+                let into_code = __rt::store(c, CODE, 101, value)?;
+                c.pc = 12;
+                if into_code {
+                    *steps += 1;
+                    return c.run();
+                }
+            }
+            // JF pos:101 imm:0
+            12 => {
+                let cond: intcode::Word = c.read_mem(101);
+                let target = 0;
+                c.pc = if cond == 0 { target } else { 15 };
+            }
+            // HALT
+            15 => {
+                return Ok(__rt::halted(c));
+            }
+            _ => return c.run(),
+        }
+        *steps += 1;
+    }
+}